@@ -0,0 +1,650 @@
+use crate::config::{ButtonConfig, DeviceConfig, DpiConfig, Format, ProfileConfig, ResolutionConfig, format_action};
+use crate::dbus::device::{ButtonAction, Edit, MouseDevice};
+use crate::dbus::error::RatbagError;
+use crate::state;
+use serde_json::json;
+use std::fmt;
+use std::io::IsTerminal;
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
+use zbus::Connection;
+
+/// How often `apply --watch` checks the file for changes.
+const WATCH_INTERVAL: Duration = Duration::from_secs(1);
+
+pub const USAGE: &str = "\
+Usage:
+  ratbagtui [--timeout SECS]             Start the TUI, giving ratbagd SECS to answer
+                                         each read or write (default 30)
+  ratbagtui list [--json]                List connected devices
+  ratbagtui info [DEVICE] [--json]       Show every setting of a device
+  ratbagtui dpi get [--profile N]        Show the DPI of the active stage
+  ratbagtui dpi set DPI [--profile N]    Set it, e.g. 1600 or 800x1200
+  ratbagtui button N get [--profile N]   Show what button N does
+  ratbagtui button N set ACTION [--profile N]
+                                         Remap button N, e.g. \"key KEY_VOLUMEUP\"
+  ratbagtui profile active get           Show the active profile
+  ratbagtui profile active set N         Switch to profile N
+  ratbagtui export [FILE] [--device NAME] [--json]
+                                         Write the device's settings to FILE (stdout if omitted)
+  ratbagtui plan FILE [--device NAME]    Show what applying FILE would change
+  ratbagtui apply FILE [--device NAME] [--watch]
+                                         Write whatever differs from FILE to the device,
+                                         and with --watch again whenever FILE changes
+  ratbagtui import FILE [--device NAME]  Same as apply
+
+Every command takes --device NAME to pick a device when several are
+connected, and --json for machine-readable output. Profiles, buttons and DPI
+stages are numbered as ratbagd numbers them, and --profile defaults to the
+active profile. FILE is JSON if it ends in .json and TOML otherwise.
+
+Exit status is 0 on success, 1 if ratbagd or the device failed, 2 for invalid
+usage, 3 for a value the device doesn't support and 4 if no device matched.";
+
+/// How long the TUI waits for ratbagd by default.
+const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+pub const EXIT_FAILURE: i32 = 1;
+pub const EXIT_USAGE: i32 = 2;
+pub const EXIT_INVALID: i32 = 3;
+pub const EXIT_NO_DEVICE: i32 = 4;
+
+/// An error that ends the process with a specific exit status.
+#[derive(Debug)]
+pub struct CliError {
+    pub code: i32,
+    message: String,
+}
+
+impl CliError {
+    fn boxed(code: i32, message: impl Into<String>) -> Box<dyn std::error::Error> {
+        Box::new(CliError {
+            code,
+            message: message.into(),
+        })
+    }
+
+    fn usage() -> Box<dyn std::error::Error> {
+        CliError::boxed(EXIT_USAGE, USAGE)
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.message)
+    }
+}
+
+impl std::error::Error for CliError {}
+
+/// Exit status for an error returned by [`run`].
+pub fn exit_code(error: &(dyn std::error::Error + 'static)) -> i32 {
+    if let Some(e) = error.downcast_ref::<CliError>() {
+        return e.code;
+    }
+    match error.downcast_ref::<RatbagError>() {
+        Some(RatbagError::OutOfRange(_) | RatbagError::Refused { .. } | RatbagError::UnsupportedAction { .. }) => {
+            EXIT_INVALID
+        }
+        _ => EXIT_FAILURE,
+    }
+}
+
+/// Print an error to stderr, followed by how to fix it if there's a known way.
+pub fn report(error: &(dyn std::error::Error + 'static)) {
+    eprintln!("ratbagtui: {}", error);
+    if let Some(hint) = error.downcast_ref::<RatbagError>().and_then(RatbagError::hint) {
+        eprintln!("hint: {}", hint);
+    }
+}
+
+/// Options for the TUI.
+pub struct TuiOptions {
+    /// How long to wait for ratbagd to read or write a device.
+    pub timeout: Duration,
+}
+
+/// Options for starting the TUI, or `None` if `args` name a subcommand.
+pub fn tui_options(args: &[String]) -> Option<Result<TuiOptions, String>> {
+    if args.first().is_some_and(|arg| !arg.starts_with("--") || arg == "--help") {
+        return None;
+    }
+    let mut options = TuiOptions {
+        timeout: DEFAULT_TIMEOUT,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--timeout" => {
+                let Some(secs) = args.next() else {
+                    return Some(Err("--timeout needs a number of seconds".into()));
+                };
+                match secs.parse::<u64>() {
+                    Ok(secs) if secs > 0 => options.timeout = Duration::from_secs(secs),
+                    _ => return Some(Err(format!("invalid timeout {:?}", secs))),
+                }
+            }
+            other => return Some(Err(format!("unknown option {}\n\n{}", other, USAGE))),
+        }
+    }
+    Some(Ok(options))
+}
+
+/// Command-line arguments after the subcommand.
+struct Args {
+    positional: Vec<String>,
+    device: Option<String>,
+    profile: Option<u32>,
+    json: bool,
+    watch: bool,
+}
+
+fn parse_args(args: &[String]) -> Result<Args, String> {
+    let mut parsed = Args {
+        positional: Vec::new(),
+        device: None,
+        profile: None,
+        json: false,
+        watch: false,
+    };
+    let mut args = args.iter();
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--device" => {
+                parsed.device = Some(args.next().ok_or("--device needs a device name")?.clone());
+            }
+            "--profile" => {
+                let profile = args.next().ok_or("--profile needs a profile number")?;
+                parsed.profile = Some(profile.parse().map_err(|_| format!("invalid profile number {:?}", profile))?);
+            }
+            "--json" => parsed.json = true,
+            "--watch" => parsed.watch = true,
+            "--" => parsed.positional.extend(args.by_ref().cloned()),
+            // Options only come in long form, so that single-dash words such
+            // as `-KEY_A` or `-d` are left alone as macro steps
+            flag if flag.starts_with("--") => return Err(format!("unknown option {}", flag)),
+            _ => parsed.positional.push(arg.clone()),
+        }
+    }
+    Ok(parsed)
+}
+
+const COMMANDS: &[&str] = &["list", "info", "dpi", "button", "profile", "export", "plan", "apply", "import"];
+
+/// Run a subcommand. `args` starts with the subcommand name.
+pub async fn run(args: &[String]) -> Result<(), Box<dyn std::error::Error>> {
+    let (command, rest) = args.split_first().ok_or_else(CliError::usage)?;
+    let args = parse_args(rest).map_err(|e| CliError::boxed(EXIT_USAGE, e))?;
+
+    // Answer help and typos without needing the system bus
+    match command.as_str() {
+        "help" | "--help" | "-h" => {
+            println!("{}", USAGE);
+            return Ok(());
+        }
+        other if !COMMANDS.contains(&other) => {
+            return Err(CliError::boxed(EXIT_USAGE, format!("unknown command {:?}\n\n{}", other, USAGE)));
+        }
+        _ => {}
+    }
+    let conn = &Connection::system().await.map_err(RatbagError::from)?;
+
+    match command.as_str() {
+        "list" => list(conn, &args).await,
+        "info" => info(conn, &args).await,
+        "dpi" => dpi(conn, &args).await,
+        "button" => button(conn, &args).await,
+        "profile" => profile(conn, &args).await,
+        "export" => export(conn, &args).await,
+        "plan" => plan(conn, &args).await,
+        _ => apply(conn, &args).await,
+    }
+}
+
+async fn load_devices(conn: &Connection) -> Result<Vec<MouseDevice>, Box<dyn std::error::Error>> {
+    let mut devices = MouseDevice::load(conn).await?;
+    devices.retain(|d| !d.profiles.is_empty());
+    if devices.is_empty() {
+        return Err(CliError::boxed(EXIT_NO_DEVICE, "No devices found. Is ratbagd running?"));
+    }
+    Ok(devices)
+}
+
+/// The device named `name`, or else the last one used in the TUI, or the only
+/// one connected.
+fn select_device(devices: &[MouseDevice], name: Option<&str>) -> Result<usize, Box<dyn std::error::Error>> {
+    let names = || devices.iter().map(|d| format!("  {}", d.name)).collect::<Vec<_>>().join("\n");
+    if let Some(name) = name {
+        return devices.iter().position(|d| d.name == name).ok_or_else(|| {
+            CliError::boxed(EXIT_NO_DEVICE, format!("No device named {:?}. Connected devices:\n{}", name, names()))
+        });
+    }
+    if devices.len() == 1 {
+        return Ok(0);
+    }
+    state::load_last_device()
+        .and_then(|last| devices.iter().position(|d| d.name == last))
+        .ok_or_else(|| {
+            CliError::boxed(
+                EXIT_NO_DEVICE,
+                format!("Several devices are connected; pick one with --device:\n{}", names()),
+            )
+        })
+}
+
+/// Load the devices and pick the one given by `--device`.
+async fn load_device(conn: &Connection, args: &Args) -> Result<MouseDevice, Box<dyn std::error::Error>> {
+    let mut devices = load_devices(conn).await?;
+    let index = select_device(&devices, args.device.as_deref())?;
+    Ok(devices.swap_remove(index))
+}
+
+/// Index into `device.profiles` of the profile given by `--profile`, or the
+/// active one.
+fn select_profile(device: &MouseDevice, args: &Args) -> Result<usize, Box<dyn std::error::Error>> {
+    match args.profile {
+        Some(index) => device
+            .profiles
+            .iter()
+            .position(|p| p.index == index)
+            .ok_or_else(|| CliError::boxed(EXIT_INVALID, format!("{} has no profile {}", device.name, index))),
+        None => Ok(device.active_profile().unwrap_or(0)),
+    }
+}
+
+/// Check `profile` against the device and write it in one commit.
+async fn write_profile(
+    conn: &Connection,
+    device: &mut MouseDevice,
+    profile: ProfileConfig,
+) -> Result<(), Box<dyn std::error::Error>> {
+    let config = DeviceConfig {
+        name: device.name.clone(),
+        profiles: vec![profile],
+    };
+    config.stage(device).map_err(|e| CliError::boxed(EXIT_INVALID, e.to_string()))?;
+    device.write(conn).await?;
+    Ok(())
+}
+
+fn print_json(value: &serde_json::Value) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+async fn list(conn: &Connection, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    if !args.positional.is_empty() {
+        return Err(CliError::usage());
+    }
+    let devices = load_devices(conn).await?;
+    if args.json {
+        let devices: Vec<_> = devices
+            .iter()
+            .map(|d| {
+                let active = d.active_profile().map(|p| &d.profiles[p]);
+                json!({
+                    "name": d.name,
+                    "profiles": d.profiles.len(),
+                    "active_profile": active.map(|p| p.index),
+                    "dpi": active.map(|p| DpiConfig::from(p.dpi())),
+                })
+            })
+            .collect();
+        return print_json(&json!(devices));
+    }
+    for device in &devices {
+        println!("{}", device.name);
+    }
+    Ok(())
+}
+
+async fn info(conn: &Connection, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let name = match args.positional.as_slice() {
+        [] => args.device.as_deref(),
+        [name] => Some(name.as_str()),
+        _ => return Err(CliError::usage()),
+    };
+    let devices = load_devices(conn).await?;
+    let device = &devices[select_device(&devices, name)?];
+    if args.json {
+        print!("{}", DeviceConfig::from_device(device).to_string(Format::Json)?);
+        return Ok(());
+    }
+
+    println!("{}", device.name);
+    for profile in &device.profiles {
+        let active = if profile.is_active { " (active)" } else { "" };
+        println!("\nProfile {}{}", profile.index, active);
+        if !profile.name.is_empty() {
+            println!("  Name: {}", profile.name);
+        }
+        if !profile.report_rates.is_empty() {
+            println!("  Report rate: {} Hz", profile.report_rate);
+        }
+        if let Some(enabled) = profile.angle_snapping {
+            println!("  Angle snapping: {}", if enabled { "on" } else { "off" });
+        }
+        if let Some(debounce) = profile.debounce {
+            println!("  Debounce: {} ms", debounce);
+        }
+        for res in &profile.resolutions {
+            let flags: Vec<&str> = [
+                (res.is_active, "active"),
+                (res.is_default, "default"),
+                (res.is_disabled, "disabled"),
+            ]
+            .iter()
+            .filter(|(set, _)| *set)
+            .map(|(_, flag)| *flag)
+            .collect();
+            let flags = if flags.is_empty() { String::new() } else { format!(" ({})", flags.join(", ")) };
+            println!("  DPI stage {}: {} dpi{}", res.index, res.dpi.label(), flags);
+        }
+        for button in &profile.buttons {
+            println!("  Button {}: {}", button.index, format_action(&button.action));
+        }
+        for led in &profile.leds {
+            let (r, g, b) = led.color;
+            println!(
+                "  LED {}: {}, #{:02x}{:02x}{:02x}, brightness {}",
+                led.index,
+                led.mode.label(),
+                r,
+                g,
+                b,
+                led.brightness
+            );
+        }
+    }
+    Ok(())
+}
+
+/// `1600`, or `800x1200` for separate X and Y resolution.
+fn parse_dpi(text: &str) -> Result<DpiConfig, Box<dyn std::error::Error>> {
+    let invalid = || CliError::boxed(EXIT_USAGE, format!("invalid DPI {:?}", text));
+    match text.split_once(['x', 'X']) {
+        Some((x, y)) => Ok(DpiConfig::Separate([
+            x.parse().map_err(|_| invalid())?,
+            y.parse().map_err(|_| invalid())?,
+        ])),
+        None => Ok(DpiConfig::Unified(text.parse().map_err(|_| invalid())?)),
+    }
+}
+
+async fn dpi(conn: &Connection, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut device = load_device(conn, args).await?;
+    let p = select_profile(&device, args)?;
+    let profile = &device.profiles[p];
+    let res = &profile.resolutions[profile.active_resolution()];
+
+    match args.positional.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["get"] => {}
+        ["set", value] => {
+            let config = ProfileConfig {
+                index: profile.index,
+                resolutions: vec![ResolutionConfig {
+                    index: res.index,
+                    dpi: Some(parse_dpi(value)?),
+                    ..Default::default()
+                }],
+                ..Default::default()
+            };
+            write_profile(conn, &mut device, config).await?;
+        }
+        _ => return Err(CliError::usage()),
+    }
+
+    let profile = &device.profiles[p];
+    let res = &profile.resolutions[profile.active_resolution()];
+    if args.json {
+        return print_json(&json!({
+            "profile": profile.index,
+            "stage": res.index,
+            "dpi": DpiConfig::from(res.dpi),
+        }));
+    }
+    println!("{}", res.dpi.label());
+    Ok(())
+}
+
+async fn button(conn: &Connection, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let (number, rest) = args.positional.split_first().ok_or_else(CliError::usage)?;
+    let number: u32 = number
+        .parse()
+        .map_err(|_| CliError::boxed(EXIT_USAGE, format!("invalid button number {:?}", number)))?;
+    let mut device = load_device(conn, args).await?;
+    let p = select_profile(&device, args)?;
+    let profile = &device.profiles[p];
+    let b = profile.buttons.iter().position(|b| b.index == number).ok_or_else(|| {
+        CliError::boxed(
+            EXIT_INVALID,
+            format!("Profile {} of {} has no button {}", profile.index, device.name, number),
+        )
+    })?;
+
+    match rest.split_first() {
+        Some((get, [])) if get == "get" => {}
+        Some((set, action)) if set == "set" && !action.is_empty() => {
+            let action = action.join(" ");
+            if crate::config::parse_action(&action).is_ok_and(|a| a == ButtonAction::Unknown) {
+                return Err(CliError::boxed(EXIT_USAGE, "\"unknown\" can't be assigned to a button"));
+            }
+            let config = ProfileConfig {
+                index: profile.index,
+                buttons: vec![ButtonConfig { index: number, action }],
+                ..Default::default()
+            };
+            write_profile(conn, &mut device, config).await?;
+        }
+        _ => return Err(CliError::usage()),
+    }
+
+    let profile = &device.profiles[p];
+    let action = &profile.buttons[b].action;
+    if args.json {
+        return print_json(&json!({
+            "profile": profile.index,
+            "button": number,
+            "action": format_action(action),
+            "label": action.label(),
+        }));
+    }
+    println!("{}", format_action(action));
+    Ok(())
+}
+
+async fn profile(conn: &Connection, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let mut device = load_device(conn, args).await?;
+
+    match args.positional.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+        ["active", "get"] => {}
+        ["active", "set", index] => {
+            let index = index
+                .parse()
+                .map_err(|_| CliError::boxed(EXIT_USAGE, format!("invalid profile number {:?}", index)))?;
+            let config = ProfileConfig {
+                index,
+                active: Some(true),
+                ..Default::default()
+            };
+            write_profile(conn, &mut device, config).await?;
+        }
+        _ => return Err(CliError::usage()),
+    }
+
+    let active = device.active_profile().map(|p| device.profiles[p].index);
+    if args.json {
+        return print_json(&json!({ "active_profile": active }));
+    }
+    match active {
+        Some(index) => println!("{}", index),
+        None => println!("none"),
+    }
+    Ok(())
+}
+
+async fn export(conn: &Connection, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let device = load_device(conn, args).await?;
+    let config = DeviceConfig::from_device(&device);
+
+    match args.positional.as_slice() {
+        [] => {
+            let format = if args.json { Format::Json } else { Format::Toml };
+            print!("{}", config.to_string(format)?);
+        }
+        [file] if file == "-" => {
+            let format = if args.json { Format::Json } else { Format::Toml };
+            print!("{}", config.to_string(format)?);
+        }
+        [file] => {
+            let path = PathBuf::from(file);
+            let format = if args.json { Format::Json } else { Format::from_path(&path) };
+            std::fs::write(&path, config.to_string(format)?).map_err(|e| format!("{}: {}", path.display(), e))?;
+            eprintln!("Exported {} to {}", device.name, path.display());
+        }
+        _ => return Err(CliError::usage()),
+    }
+    Ok(())
+}
+
+fn config_path(args: &Args) -> Result<&Path, Box<dyn std::error::Error>> {
+    match args.positional.as_slice() {
+        [file] => Ok(Path::new(file)),
+        _ => Err(CliError::usage()),
+    }
+}
+
+/// Load `path` and stage it on the matching device, read fresh from ratbagd.
+async fn stage_config(conn: &Connection, args: &Args, path: &Path) -> Result<MouseDevice, Box<dyn std::error::Error>> {
+    let config = DeviceConfig::load(path)?;
+    let mut devices = load_devices(conn).await?;
+    let name = args.device.as_deref().unwrap_or(&config.name);
+    let mut device = devices.swap_remove(select_device(&devices, Some(name))?);
+    config.stage(&mut device).map_err(|e| CliError::boxed(EXIT_INVALID, e.to_string()))?;
+    Ok(device)
+}
+
+/// Whether to colour output: only on a terminal, and not with `NO_COLOR` set.
+fn use_colour() -> bool {
+    std::io::stdout().is_terminal() && std::env::var_os("NO_COLOR").is_none()
+}
+
+/// Wrap `text` in an ANSI SGR sequence when colouring output.
+fn paint(code: &str, text: &str) -> String {
+    if use_colour() {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
+    } else {
+        text.to_string()
+    }
+}
+
+fn print_plan(device: &MouseDevice, changes: &[Edit], path: &Path) {
+    if changes.is_empty() {
+        println!("No changes. {} matches {}.", device.name, path.display());
+        return;
+    }
+    println!("{}", paint("1", &device.name));
+    for change in changes {
+        println!(
+            "  {} {}: {} → {}",
+            paint("33", "~"),
+            change.after.target(),
+            paint("31", &change.before.value_label()),
+            paint("32", &change.after.value_label())
+        );
+    }
+    println!(
+        "\nPlan: {} change{} to apply.",
+        changes.len(),
+        if changes.len() == 1 { "" } else { "s" }
+    );
+}
+
+fn plan_json(device: &MouseDevice, changes: &[Edit]) -> serde_json::Value {
+    let changes: Vec<_> = changes
+        .iter()
+        .map(|c| {
+            json!({
+                "target": c.after.target(),
+                "before": c.before.value_label(),
+                "after": c.after.value_label(),
+            })
+        })
+        .collect();
+    json!({ "device": device.name, "changes": changes })
+}
+
+async fn plan(conn: &Connection, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let path = config_path(args)?;
+    let device = stage_config(conn, args, path).await?;
+    if args.json {
+        return print_json(&plan_json(&device, &device.pending_changes()));
+    }
+    print_plan(&device, &device.pending_changes(), path);
+    Ok(())
+}
+
+/// Write whatever differs between `path` and the device, in one commit.
+async fn apply_once(conn: &Connection, args: &Args, path: &Path) -> Result<(), Box<dyn std::error::Error>> {
+    let mut device = stage_config(conn, args, path).await?;
+    let changes = device.pending_changes();
+    if !args.json {
+        print_plan(&device, &changes, path);
+    }
+    if !changes.is_empty() {
+        let n = device.write(conn).await?;
+        if !args.json {
+            println!("Applied {} change{} to {}.", n, if n == 1 { "" } else { "s" }, device.name);
+        }
+    }
+    if args.json {
+        print_json(&plan_json(&device, &changes))?;
+    }
+    Ok(())
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path).and_then(|m| m.modified()).ok()
+}
+
+async fn apply(conn: &Connection, args: &Args) -> Result<(), Box<dyn std::error::Error>> {
+    let path = config_path(args)?;
+    if !args.watch {
+        return apply_once(conn, args, path).await;
+    }
+
+    let mut last = modified(path);
+    if let Err(e) = apply_once(conn, args, path).await {
+        report(e.as_ref());
+    }
+    eprintln!("Watching {} for changes (Ctrl-C to stop)", path.display());
+    loop {
+        tokio::time::sleep(WATCH_INTERVAL).await;
+        let now = modified(path);
+        if now == last {
+            continue;
+        }
+        last = now;
+        // Errors such as a half-saved file shouldn't end the watch
+        if let Err(e) = apply_once(conn, args, path).await {
+            report(e.as_ref());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn args(words: &[&str]) -> Vec<String> {
+        words.iter().map(|w| w.to_string()).collect()
+    }
+
+    #[test]
+    fn single_dash_words_are_macro_steps() {
+        let parsed = parse_args(&args(&["5", "set", "macro", "+d", "-d", "-p", "--profile", "1"])).unwrap();
+        assert_eq!(parsed.positional, args(&["5", "set", "macro", "+d", "-d", "-p"]));
+        assert_eq!(parsed.device, None);
+        assert_eq!(parsed.profile, Some(1));
+    }
+}
@@ -0,0 +1,271 @@
+/// Row of the colour picker that arrow keys currently adjust.
+#[derive(Clone, Copy, PartialEq)]
+pub enum PickerField {
+    Hex,
+    Red,
+    Green,
+    Blue,
+    Hue,
+    Saturation,
+    Value,
+}
+
+impl PickerField {
+    pub const ALL: [PickerField; 7] = [
+        PickerField::Hex,
+        PickerField::Red,
+        PickerField::Green,
+        PickerField::Blue,
+        PickerField::Hue,
+        PickerField::Saturation,
+        PickerField::Value,
+    ];
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            PickerField::Hex => "Hex",
+            PickerField::Red => "R",
+            PickerField::Green => "G",
+            PickerField::Blue => "B",
+            PickerField::Hue => "H",
+            PickerField::Saturation => "S",
+            PickerField::Value => "V",
+        }
+    }
+
+    /// Largest value the slider for this field can take.
+    pub fn max(&self) -> u32 {
+        match self {
+            PickerField::Hex => 0,
+            PickerField::Red | PickerField::Green | PickerField::Blue => 255,
+            PickerField::Hue => 359,
+            PickerField::Saturation | PickerField::Value => 100,
+        }
+    }
+}
+
+/// Editing state for the LED colour popup.
+///
+/// RGB and HSV are both kept so that dragging saturation or value down to
+/// zero doesn't lose the hue the user picked.
+pub struct ColorPicker {
+    pub rgb: (u8, u8, u8),
+    pub hsv: (u16, u8, u8),
+    pub field: PickerField,
+    pub hex_input: String,
+}
+
+impl ColorPicker {
+    pub fn new(rgb: (u8, u8, u8)) -> Self {
+        ColorPicker {
+            rgb,
+            hsv: rgb_to_hsv(rgb),
+            field: PickerField::Red,
+            hex_input: to_hex(rgb),
+        }
+    }
+
+    pub fn next_field(&mut self) {
+        let i = PickerField::ALL.iter().position(|&f| f == self.field).unwrap_or(0);
+        if i + 1 < PickerField::ALL.len() {
+            self.field = PickerField::ALL[i + 1];
+        }
+    }
+
+    pub fn prev_field(&mut self) {
+        let i = PickerField::ALL.iter().position(|&f| f == self.field).unwrap_or(0);
+        if i > 0 {
+            self.field = PickerField::ALL[i - 1];
+        }
+    }
+
+    pub fn value(&self, field: PickerField) -> u32 {
+        match field {
+            PickerField::Hex => 0,
+            PickerField::Red => self.rgb.0 as u32,
+            PickerField::Green => self.rgb.1 as u32,
+            PickerField::Blue => self.rgb.2 as u32,
+            PickerField::Hue => self.hsv.0 as u32,
+            PickerField::Saturation => self.hsv.1 as u32,
+            PickerField::Value => self.hsv.2 as u32,
+        }
+    }
+
+    /// Move the selected slider by `delta`, clamped to its range.
+    pub fn adjust(&mut self, delta: i32) {
+        let field = self.field;
+        if field == PickerField::Hex {
+            return;
+        }
+        let v = (self.value(field) as i32 + delta).clamp(0, field.max() as i32);
+        match field {
+            PickerField::Hex => {}
+            PickerField::Red => self.set_rgb((v as u8, self.rgb.1, self.rgb.2)),
+            PickerField::Green => self.set_rgb((self.rgb.0, v as u8, self.rgb.2)),
+            PickerField::Blue => self.set_rgb((self.rgb.0, self.rgb.1, v as u8)),
+            PickerField::Hue => self.set_hsv((v as u16, self.hsv.1, self.hsv.2)),
+            PickerField::Saturation => self.set_hsv((self.hsv.0, v as u8, self.hsv.2)),
+            PickerField::Value => self.set_hsv((self.hsv.0, self.hsv.1, v as u8)),
+        }
+    }
+
+    pub fn push_hex(&mut self, c: char) {
+        if self.field != PickerField::Hex || !c.is_ascii_hexdigit() {
+            return;
+        }
+        if self.hex_input.len() >= 6 {
+            self.hex_input.clear();
+        }
+        self.hex_input.push(c.to_ascii_lowercase());
+        if let Some(rgb) = parse_hex(&self.hex_input) {
+            self.rgb = rgb;
+            self.hsv = rgb_to_hsv(rgb);
+        }
+    }
+
+    pub fn pop_hex(&mut self) {
+        if self.field == PickerField::Hex {
+            self.hex_input.pop();
+        }
+    }
+
+    fn set_rgb(&mut self, rgb: (u8, u8, u8)) {
+        self.rgb = rgb;
+        self.hsv = rgb_to_hsv(rgb);
+        self.hex_input = to_hex(rgb);
+    }
+
+    fn set_hsv(&mut self, hsv: (u16, u8, u8)) {
+        self.hsv = hsv;
+        self.rgb = hsv_to_rgb(hsv);
+        self.hex_input = to_hex(self.rgb);
+    }
+}
+
+pub fn to_hex((r, g, b): (u8, u8, u8)) -> String {
+    format!("{:02x}{:02x}{:02x}", r, g, b)
+}
+
+/// Parse `rrggbb`, with or without a leading `#`.
+pub fn parse_hex(s: &str) -> Option<(u8, u8, u8)> {
+    let s = s.trim_start_matches('#');
+    if s.len() != 6 || !s.is_ascii() {
+        return None;
+    }
+    let r = u8::from_str_radix(&s[0..2], 16).ok()?;
+    let g = u8::from_str_radix(&s[2..4], 16).ok()?;
+    let b = u8::from_str_radix(&s[4..6], 16).ok()?;
+    Some((r, g, b))
+}
+
+/// Hue in degrees, saturation and value in percent.
+pub fn rgb_to_hsv((r, g, b): (u8, u8, u8)) -> (u16, u8, u8) {
+    let (r, g, b) = (r as f32 / 255.0, g as f32 / 255.0, b as f32 / 255.0);
+    let max = r.max(g).max(b);
+    let min = r.min(g).min(b);
+    let delta = max - min;
+
+    let h = if delta == 0.0 {
+        0.0
+    } else if max == r {
+        60.0 * ((g - b) / delta).rem_euclid(6.0)
+    } else if max == g {
+        60.0 * ((b - r) / delta + 2.0)
+    } else {
+        60.0 * ((r - g) / delta + 4.0)
+    };
+    let s = if max == 0.0 { 0.0 } else { delta / max };
+
+    ((h.round() as u16) % 360, (s * 100.0).round() as u8, (max * 100.0).round() as u8)
+}
+
+pub fn hsv_to_rgb((h, s, v): (u16, u8, u8)) -> (u8, u8, u8) {
+    let s = s as f32 / 100.0;
+    let v = v as f32 / 100.0;
+    let c = v * s;
+    let hp = (h % 360) as f32 / 60.0;
+    let x = c * (1.0 - (hp.rem_euclid(2.0) - 1.0).abs());
+    let (r, g, b) = match hp as u32 {
+        0 => (c, x, 0.0),
+        1 => (x, c, 0.0),
+        2 => (0.0, c, x),
+        3 => (0.0, x, c),
+        4 => (x, 0.0, c),
+        _ => (c, 0.0, x),
+    };
+    let m = v - c;
+    let to_u8 = |f: f32| ((f + m) * 255.0).round() as u8;
+    (to_u8(r), to_u8(g), to_u8(b))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parse_hex_accepts_only_six_digits() {
+        let cases = [
+            ("#ff8000", Some((255, 128, 0))),
+            ("FF8000", Some((255, 128, 0))),
+            ("#000000", Some((0, 0, 0))),
+            ("#fff", None),
+            ("#ff80001", None),
+            ("#gg0000", None),
+            ("", None),
+            // Six bytes, but not six hex digits
+            ("aé000", None),
+        ];
+        for (text, expected) in cases {
+            assert_eq!(parse_hex(text), expected, "{:?}", text);
+        }
+    }
+
+    #[test]
+    fn hex_round_trip() {
+        for rgb in [(0, 0, 0), (255, 255, 255), (18, 52, 86), (171, 205, 239)] {
+            assert_eq!(parse_hex(&to_hex(rgb)), Some(rgb));
+        }
+    }
+
+    #[test]
+    fn rgb_to_hsv_cases() {
+        let cases = [
+            ((255, 0, 0), (0, 100, 100)),
+            ((0, 255, 0), (120, 100, 100)),
+            ((0, 0, 255), (240, 100, 100)),
+            ((255, 0, 255), (300, 100, 100)),
+            // Grey has no hue or saturation
+            ((0, 0, 0), (0, 0, 0)),
+            ((128, 128, 128), (0, 0, 50)),
+            ((255, 255, 255), (0, 0, 100)),
+            // Just short of 360 degrees rounds round to red
+            ((255, 0, 1), (0, 100, 100)),
+        ];
+        for (rgb, hsv) in cases {
+            assert_eq!(rgb_to_hsv(rgb), hsv, "{:?}", rgb);
+        }
+    }
+
+    #[test]
+    fn hsv_to_rgb_wraps_hue() {
+        assert_eq!(hsv_to_rgb((0, 100, 100)), (255, 0, 0));
+        assert_eq!(hsv_to_rgb((360, 100, 100)), (255, 0, 0));
+        assert_eq!(hsv_to_rgb((480, 100, 100)), hsv_to_rgb((120, 100, 100)));
+        assert_eq!(hsv_to_rgb((200, 0, 50)), (128, 128, 128));
+    }
+
+    #[test]
+    fn hsv_round_trip_is_close() {
+        // HSV in whole degrees and percent loses a little precision
+        for r in (0..=255).step_by(51) {
+            for g in (0..=255).step_by(51) {
+                for b in (0..=255).step_by(51) {
+                    let (r2, g2, b2) = hsv_to_rgb(rgb_to_hsv((r, g, b)));
+                    for (a, b) in [(r, r2), (g, g2), (b, b2)] {
+                        assert!(a.abs_diff(b) <= 3, "{:?} came back as {:?}", (r, g, b), (r2, g2, b2));
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,723 @@
+use crate::color::{parse_hex, to_hex};
+use crate::dbus::device::{
+    ButtonAction, Dpi, LedMode, MacroEvent, MouseDevice, MouseLed, SPECIAL_ACTIONS, SPECIAL_UNKNOWN,
+};
+use crate::keys::{key_name, lookup_key};
+use serde::{Deserialize, Serialize};
+use std::path::Path;
+
+/// File format for exported configuration, picked from the file extension.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Format {
+    Toml,
+    Json,
+}
+
+impl Format {
+    /// JSON for `.json` files, TOML for anything else.
+    pub fn from_path(path: &Path) -> Self {
+        match path.extension().and_then(|e| e.to_str()) {
+            Some(ext) if ext.eq_ignore_ascii_case("json") => Format::Json,
+            _ => Format::Toml,
+        }
+    }
+}
+
+/// Everything ratbagtui can configure on a device, as written to and read
+/// from a configuration file. Profiles, stages, buttons and LEDs are matched
+/// to the device by their ratbagd index. Anything left out of the file is
+/// left as it is on the device when importing.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct DeviceConfig {
+    pub name: String,
+    #[serde(default)]
+    pub profiles: Vec<ProfileConfig>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ProfileConfig {
+    pub index: u32,
+    /// Shown for reference; profile names are not imported.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub name: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report_rate: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub angle_snapping: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub debounce: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub resolutions: Vec<ResolutionConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub buttons: Vec<ButtonConfig>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub leds: Vec<LedConfig>,
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct ResolutionConfig {
+    pub index: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub dpi: Option<DpiConfig>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub active: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub default: Option<bool>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub disabled: Option<bool>,
+}
+
+/// `800`, or `[800, 1200]` for separate X and Y resolution.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum DpiConfig {
+    Unified(u32),
+    Separate([u32; 2]),
+}
+
+impl From<Dpi> for DpiConfig {
+    fn from(dpi: Dpi) -> Self {
+        match dpi {
+            Dpi::Unified(dpi) => DpiConfig::Unified(dpi),
+            Dpi::Separate { x, y } => DpiConfig::Separate([x, y]),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ButtonConfig {
+    pub index: u32,
+    /// See [`format_action`] for the syntax.
+    pub action: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct LedConfig {
+    pub index: u32,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub mode: Option<String>,
+    /// `#rrggbb`
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub color: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub brightness: Option<u32>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub effect_duration: Option<u32>,
+}
+
+impl DeviceConfig {
+    /// Snapshot of the device's settings, including any staged changes.
+    pub fn from_device(device: &MouseDevice) -> Self {
+        let profiles = device
+            .profiles
+            .iter()
+            .map(|p| ProfileConfig {
+                index: p.index,
+                name: (!p.name.is_empty()).then(|| p.name.clone()),
+                active: Some(p.is_active),
+                report_rate: (!p.report_rates.is_empty()).then_some(p.report_rate),
+                angle_snapping: p.angle_snapping,
+                debounce: p.debounce,
+                resolutions: p
+                    .resolutions
+                    .iter()
+                    .map(|r| ResolutionConfig {
+                        index: r.index,
+                        dpi: Some(r.dpi.into()),
+                        active: Some(r.is_active),
+                        default: Some(r.is_default),
+                        disabled: Some(r.is_disabled),
+                    })
+                    .collect(),
+                buttons: p
+                    .buttons
+                    .iter()
+                    .map(|b| ButtonConfig {
+                        index: b.index,
+                        action: format_action(&b.action),
+                    })
+                    .collect(),
+                leds: p
+                    .leds
+                    .iter()
+                    .map(|l| LedConfig {
+                        index: l.index,
+                        mode: Some(format_led_mode(l.mode)),
+                        color: (!l.is_monochrome()).then(|| {
+                            let (r, g, b) = l.color;
+                            format!("#{}", to_hex((r as u8, g as u8, b as u8)))
+                        }),
+                        brightness: Some(l.brightness),
+                        effect_duration: l.mode.is_animated().then_some(l.effect_duration),
+                    })
+                    .collect(),
+            })
+            .collect();
+
+        DeviceConfig {
+            name: device.name.clone(),
+            profiles,
+        }
+    }
+
+    pub fn to_string(&self, format: Format) -> Result<String, Box<dyn std::error::Error>> {
+        Ok(match format {
+            Format::Toml => toml::to_string(self)?,
+            Format::Json => serde_json::to_string_pretty(self)? + "\n",
+        })
+    }
+
+    pub fn from_str(text: &str, format: Format) -> Result<Self, Box<dyn std::error::Error>> {
+        Ok(match format {
+            Format::Toml => toml::from_str(text)?,
+            Format::Json => serde_json::from_str(text)?,
+        })
+    }
+
+    pub fn load(path: &Path) -> Result<Self, Box<dyn std::error::Error>> {
+        let text = std::fs::read_to_string(path).map_err(|e| format!("{}: {}", path.display(), e))?;
+        DeviceConfig::from_str(&text, Format::from_path(path)).map_err(|e| format!("{}: {}", path.display(), e).into())
+    }
+
+    /// Stage the configuration on `device`. Every value is checked against
+    /// what the device supports first; if anything is out of range nothing is
+    /// staged and the error lists every problem found.
+    pub fn stage(&self, device: &mut MouseDevice) -> Result<(), Box<dyn std::error::Error>> {
+        let mut staged = device.clone();
+        let mut problems = Vec::new();
+
+        for profile_config in &self.profiles {
+            let Some(p) = staged.profiles.iter().position(|p| p.index == profile_config.index) else {
+                problems.push(format!("profile {}: no such profile", profile_config.index));
+                continue;
+            };
+            let mut problem = |what: String| problems.push(format!("profile {}, {}", profile_config.index, what));
+
+            if profile_config.active == Some(true) {
+                staged.set_active_profile(p);
+            }
+
+            if let Some(rate) = profile_config.report_rate {
+                let rates = &staged.profiles[p].report_rates;
+                if rates.contains(&rate) {
+                    staged.set_report_rate(p, rate);
+                } else {
+                    problem(format!("report rate {} Hz not in {:?}", rate, rates));
+                }
+            }
+
+            if let Some(enabled) = profile_config.angle_snapping {
+                if staged.profiles[p].angle_snapping.is_some() {
+                    staged.set_angle_snapping(p, enabled);
+                } else {
+                    problem("angle snapping is not supported".into());
+                }
+            }
+
+            if let Some(debounce) = profile_config.debounce {
+                let debounces = &staged.profiles[p].debounces;
+                if staged.profiles[p].debounce.is_none() {
+                    problem("debounce time is not supported".into());
+                } else if debounces.is_empty() || debounces.contains(&debounce) {
+                    staged.set_debounce(p, debounce);
+                } else {
+                    problem(format!("debounce {} ms not in {:?}", debounce, debounces));
+                }
+            }
+
+            for res_config in &profile_config.resolutions {
+                let resolutions = &staged.profiles[p].resolutions;
+                let Some(r) = resolutions.iter().position(|r| r.index == res_config.index) else {
+                    problem(format!("DPI stage {}: no such stage", res_config.index));
+                    continue;
+                };
+                if let Some(dpi) = res_config.dpi {
+                    let res = &resolutions[r];
+                    let dpi = match (dpi, res.dpi) {
+                        (DpiConfig::Unified(dpi), Dpi::Unified(_)) => Some(Dpi::Unified(dpi)),
+                        (DpiConfig::Unified(dpi), Dpi::Separate { .. }) => Some(Dpi::Separate { x: dpi, y: dpi }),
+                        (DpiConfig::Separate([x, y]), Dpi::Separate { .. }) => Some(Dpi::Separate { x, y }),
+                        (DpiConfig::Separate(_), Dpi::Unified(_)) => {
+                            problem(format!("DPI stage {}: separate X/Y DPI is not supported", res.index));
+                            None
+                        }
+                    };
+                    if let Some(dpi) = dpi {
+                        let valid = |v: u32| res.valid_dpis.is_empty() || res.valid_dpis.contains(&v);
+                        if valid(dpi.x()) && valid(dpi.y()) {
+                            staged.set_dpi(p, r, dpi);
+                        } else {
+                            problem(format!("DPI stage {}: {} dpi is not supported", res.index, dpi.label()));
+                        }
+                    }
+                }
+                // The mouse needs an enabled stage to use and to start with
+                let (active, default) = (res_config.active == Some(true), res_config.default == Some(true));
+                if res_config.disabled == Some(true) && (active || default) {
+                    let role = if active { "active" } else { "default" };
+                    problem(format!("DPI stage {}: the {} stage can't be disabled", res_config.index, role));
+                    continue;
+                }
+                if res_config.disabled.is_some_and(|d| d != staged.profiles[p].resolutions[r].is_disabled) {
+                    staged.set_resolution_disabled(p, r, res_config.disabled == Some(true));
+                }
+                if active {
+                    staged.set_active_resolution(p, r);
+                }
+                if default {
+                    staged.set_default_resolution(p, r);
+                }
+            }
+
+            for button_config in &profile_config.buttons {
+                let buttons = &staged.profiles[p].buttons;
+                let Some(b) = buttons.iter().position(|b| b.index == button_config.index) else {
+                    problem(format!("button {}: no such button", button_config.index));
+                    continue;
+                };
+                let action = match parse_action(&button_config.action) {
+                    Ok(action) => action,
+                    Err(e) => {
+                        problem(format!("button {}: {}", button_config.index, e));
+                        continue;
+                    }
+                };
+                let button = &buttons[b];
+                // An exported mapping ratbagd couldn't name is left as it is
+                if action == button.action {
+                    continue;
+                }
+                if action == ButtonAction::Special(SPECIAL_UNKNOWN) {
+                    problem(format!("button {}: the unknown special action can't be set", button.index));
+                    continue;
+                }
+                if action == ButtonAction::None {
+                    if button.supports_none() {
+                        staged.set_button(p, b, action);
+                    } else {
+                        problem(format!("button {}: can't be disabled", button.index));
+                    }
+                    continue;
+                }
+                // `unknown` leaves the mapping alone
+                let Some(category) = action.category() else {
+                    continue;
+                };
+                if button.supports(category) {
+                    staged.set_button(p, b, action);
+                } else {
+                    problem(format!("button {}: {} are not supported", button.index, category.label()));
+                }
+            }
+
+            for led_config in &profile_config.leds {
+                let leds = &staged.profiles[p].leds;
+                let Some(l) = leds.iter().position(|l| l.index == led_config.index) else {
+                    problem(format!("LED {}: no such LED", led_config.index));
+                    continue;
+                };
+                let led = &leds[l];
+                let index = led.index;
+                let mode = match led_config.mode.as_deref().map(parse_led_mode) {
+                    Some(Some(mode)) if led.modes.contains(&mode) => Some(mode),
+                    Some(Some(mode)) => {
+                        problem(format!("LED {}: mode {} is not supported", index, mode.label()));
+                        None
+                    }
+                    Some(None) => {
+                        problem(format!("LED {}: unknown mode {:?}", index, led_config.mode.as_deref().unwrap_or("")));
+                        None
+                    }
+                    None => None,
+                };
+                let color = match led_config.color.as_deref().map(parse_hex) {
+                    Some(Some((r, g, b))) => Some((r as u32, g as u32, b as u32)),
+                    Some(None) => {
+                        problem(format!("LED {}: colour {:?} is not #rrggbb", index, led_config.color.as_deref().unwrap_or("")));
+                        None
+                    }
+                    None => None,
+                };
+                if let Some(brightness) = led_config.brightness
+                    && brightness > MouseLed::MAX_BRIGHTNESS
+                {
+                    problem(format!("LED {}: brightness {} is above {}", index, brightness, MouseLed::MAX_BRIGHTNESS));
+                }
+                if let Some(duration) = led_config.effect_duration
+                    && duration > MouseLed::MAX_EFFECT_DURATION
+                {
+                    problem(format!(
+                        "LED {}: effect duration {} ms is above {}",
+                        index,
+                        duration,
+                        MouseLed::MAX_EFFECT_DURATION
+                    ));
+                }
+
+                if let Some(mode) = mode {
+                    staged.set_led_mode(p, l, mode);
+                }
+                if let Some(color) = color {
+                    staged.set_led_color(p, l, color);
+                }
+                if let Some(brightness) = led_config.brightness {
+                    staged.set_led_brightness(p, l, brightness);
+                }
+                if let Some(duration) = led_config.effect_duration {
+                    staged.set_led_effect_duration(p, l, duration);
+                }
+            }
+        }
+
+        if !problems.is_empty() {
+            return Err(format!("invalid configuration for {}:\n  {}", device.name, problems.join("\n  ")).into());
+        }
+        *device = staged;
+        Ok(())
+    }
+}
+
+/// Config name of a special action: its label in kebab case, e.g.
+/// `resolution-alternate-sniper`.
+fn special_slug(name: &str) -> String {
+    name.to_ascii_lowercase()
+        .split(|c: char| !c.is_ascii_alphanumeric())
+        .filter(|w| !w.is_empty())
+        .collect::<Vec<_>>()
+        .join("-")
+}
+
+/// Button action as written in a configuration file:
+///
+/// - `none`
+/// - `button 2`
+/// - `special resolution-cycle-up`
+/// - `key KEY_VOLUMEUP`
+/// - `macro +KEY_LEFTCTRL +KEY_C -KEY_C -KEY_LEFTCTRL 50ms`, where `+` presses
+///   a key, `-` releases it and `50ms` waits
+/// - `unknown`, for mappings ratbagtui can't represent; ignored on import
+pub fn format_action(action: &ButtonAction) -> String {
+    let key = |code: u32| key_name(code).map_or_else(|| code.to_string(), str::to_string);
+    match action {
+        ButtonAction::None => "none".into(),
+        ButtonAction::Button(n) => format!("button {}", n),
+        ButtonAction::Special(n) => match SPECIAL_ACTIONS.iter().find(|(v, _)| v == n) {
+            Some((_, name)) => format!("special {}", special_slug(name)),
+            None => format!("special {:#x}", n),
+        },
+        ButtonAction::Key(code) => format!("key {}", key(*code)),
+        ButtonAction::Macro(steps) => {
+            let mut out = String::from("macro");
+            for step in steps {
+                out.push(' ');
+                match *step {
+                    MacroEvent::KeyPress(code) => out.push_str(&format!("+{}", key(code))),
+                    MacroEvent::KeyRelease(code) => out.push_str(&format!("-{}", key(code))),
+                    MacroEvent::Wait(ms) => out.push_str(&format!("{}ms", ms)),
+                }
+            }
+            out
+        }
+        ButtonAction::Unknown => "unknown".into(),
+    }
+}
+
+/// Parse an action written by [`format_action`]. Keys may be given by name or
+/// by keycode, and special actions by name or by value.
+pub fn parse_action(text: &str) -> Result<ButtonAction, String> {
+    let mut words = text.split_whitespace();
+    let kind = words.next().unwrap_or("").to_ascii_lowercase();
+    let args: Vec<&str> = words.collect();
+    let key = |word: &str| lookup_key(word).ok_or_else(|| format!("unknown key {:?}", word));
+    let single = || match args.as_slice() {
+        [arg] => Ok(*arg),
+        _ => Err(format!("{:?} takes exactly one argument", kind)),
+    };
+
+    match kind.as_str() {
+        "none" if args.is_empty() => Ok(ButtonAction::None),
+        "unknown" if args.is_empty() => Ok(ButtonAction::Unknown),
+        "button" => {
+            let arg = single()?;
+            arg.parse().map(ButtonAction::Button).map_err(|_| format!("invalid button number {:?}", arg))
+        }
+        "special" => {
+            let arg = single()?;
+            let slug = special_slug(arg);
+            if let Some((value, _)) = SPECIAL_ACTIONS.iter().find(|(_, name)| special_slug(name) == slug) {
+                return Ok(ButtonAction::Special(*value));
+            }
+            let value = match arg.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16).ok(),
+                None => arg.parse().ok(),
+            };
+            match value {
+                Some(v) if v >= SPECIAL_UNKNOWN => Ok(ButtonAction::Special(v)),
+                _ => Err(format!("unknown special action {:?}", arg)),
+            }
+        }
+        "key" => Ok(ButtonAction::Key(key(single()?)?)),
+        "macro" => {
+            let steps = args
+                .iter()
+                .map(|word| {
+                    if let Some(name) = word.strip_prefix('+') {
+                        key(name).map(MacroEvent::KeyPress)
+                    } else if let Some(name) = word.strip_prefix('-') {
+                        key(name).map(MacroEvent::KeyRelease)
+                    } else if let Some(ms) = word.strip_suffix("ms").and_then(|ms| ms.parse().ok()) {
+                        Ok(MacroEvent::Wait(ms))
+                    } else {
+                        Err(format!("invalid macro step {:?}", word))
+                    }
+                })
+                .collect::<Result<Vec<_>, _>>()?;
+            Ok(ButtonAction::Macro(steps))
+        }
+        _ => Err(format!("invalid action {:?}", text)),
+    }
+}
+
+fn format_led_mode(mode: LedMode) -> String {
+    match mode {
+        LedMode::Unknown(n) => format!("mode {}", n),
+        other => other.label().to_ascii_lowercase(),
+    }
+}
+
+fn parse_led_mode(text: &str) -> Option<LedMode> {
+    let text = text.trim().to_ascii_lowercase();
+    match text.strip_prefix("mode ") {
+        Some(n) => n.trim().parse().ok().map(LedMode::from_u32),
+        None => [LedMode::Off, LedMode::On, LedMode::Cycle, LedMode::Breathing]
+            .into_iter()
+            .find(|m| m.label().eq_ignore_ascii_case(&text)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::dbus::device::{Change, MouseButton, MouseProfile, MouseResolution};
+    use zbus::zvariant::OwnedObjectPath;
+
+    fn path(p: &str) -> OwnedObjectPath {
+        OwnedObjectPath::try_from(p).unwrap()
+    }
+
+    /// One profile with one DPI stage, numbered 0, and two buttons, the
+    /// second mapped to a special action ratbagd couldn't name.
+    fn device() -> MouseDevice {
+        let resolution = MouseResolution {
+            index: 0,
+            dpi: Dpi::Unified(800),
+            valid_dpis: vec![400, 800, 1600],
+            is_active: true,
+            is_default: true,
+            is_disabled: false,
+            path: path("/test/p0/r0"),
+        };
+        let button = MouseButton {
+            index: 0,
+            action: ButtonAction::Button(1),
+            action_types: Vec::new(),
+            path: path("/test/p0/b0"),
+        };
+        let unknown = MouseButton {
+            index: 1,
+            action: ButtonAction::Special(SPECIAL_UNKNOWN),
+            action_types: Vec::new(),
+            path: path("/test/p0/b1"),
+        };
+        let profile = MouseProfile {
+            index: 0,
+            name: String::new(),
+            is_active: true,
+            resolutions: vec![resolution],
+            report_rate: 1000,
+            report_rates: vec![500, 1000],
+            angle_snapping: None,
+            debounce: None,
+            debounces: Vec::new(),
+            buttons: vec![button, unknown],
+            leds: Vec::new(),
+            path: path("/test/p0"),
+        };
+        MouseDevice::new("Test Mouse".into(), path("/test"), vec![profile])
+    }
+
+    #[test]
+    fn actions_round_trip() {
+        let volume_up = lookup_key("KEY_VOLUMEUP").unwrap();
+        let ctrl = lookup_key("KEY_LEFTCTRL").unwrap();
+        let c = lookup_key("KEY_C").unwrap();
+        let mut actions = vec![
+            ButtonAction::None,
+            ButtonAction::Button(3),
+            ButtonAction::Key(volume_up),
+            ButtonAction::Macro(vec![
+                MacroEvent::KeyPress(ctrl),
+                MacroEvent::KeyPress(c),
+                MacroEvent::Wait(50),
+                MacroEvent::KeyRelease(c),
+                MacroEvent::KeyRelease(ctrl),
+            ]),
+            ButtonAction::Macro(Vec::new()),
+        ];
+        actions.extend(SPECIAL_ACTIONS.iter().map(|(value, _)| ButtonAction::Special(*value)));
+        actions.push(ButtonAction::Special(SPECIAL_UNKNOWN));
+
+        for action in actions {
+            let text = format_action(&action);
+            assert_eq!(parse_action(&text), Ok(action), "{}", text);
+        }
+    }
+
+    #[test]
+    fn malformed_macros_are_rejected() {
+        for text in ["macro KEY_A", "macro +KEY_A 50", "macro 50s", "macro +", "macro -"] {
+            assert!(parse_action(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn unknown_keys_are_rejected() {
+        for text in ["key KEY_NOPE", "key", "key KEY_A KEY_B", "macro +KEY_NOPE", "macro -NOPE"] {
+            assert!(parse_action(text).is_err(), "{}", text);
+        }
+    }
+
+    #[test]
+    fn stage_reports_missing_indexes() {
+        let config = DeviceConfig {
+            name: "Test Mouse".into(),
+            profiles: vec![
+                ProfileConfig {
+                    index: 5,
+                    ..Default::default()
+                },
+                ProfileConfig {
+                    index: 0,
+                    report_rate: Some(250),
+                    resolutions: vec![
+                        ResolutionConfig {
+                            index: 3,
+                            dpi: Some(DpiConfig::Unified(1600)),
+                            ..Default::default()
+                        },
+                        ResolutionConfig {
+                            index: 0,
+                            active: Some(true),
+                            disabled: Some(true),
+                            ..Default::default()
+                        },
+                    ],
+                    buttons: vec![
+                        ButtonConfig {
+                            index: 7,
+                            action: "button 2".into(),
+                        },
+
+                        ButtonConfig {
+                            index: 0,
+                            action: "button 3".into(),
+                        },
+                    ],
+                    ..Default::default()
+                },
+            ],
+        };
+
+        let mut device = device();
+        let error = config.stage(&mut device).unwrap_err().to_string();
+        assert!(error.contains("profile 5: no such profile"), "{}", error);
+        assert!(error.contains("profile 0, report rate 250 Hz not in [500, 1000]"), "{}", error);
+        assert!(error.contains("profile 0, DPI stage 3: no such stage"), "{}", error);
+        assert!(error.contains("profile 0, button 7: no such button"), "{}", error);
+        assert!(error.contains("profile 0, DPI stage 0: the active stage can't be disabled"), "{}", error);
+        // Nothing is staged, not even the valid button change
+        assert!(device.pending_changes().is_empty());
+    }
+
+    #[test]
+    fn stage_applies_valid_config() {
+        let config = DeviceConfig {
+            name: "Test Mouse".into(),
+            profiles: vec![ProfileConfig {
+                index: 0,
+                resolutions: vec![ResolutionConfig {
+                    index: 0,
+                    dpi: Some(DpiConfig::Unified(1600)),
+                    ..Default::default()
+                }],
+                buttons: vec![ButtonConfig {
+                    index: 0,
+                    action: "key KEY_VOLUMEUP".into(),
+                }],
+                ..Default::default()
+            }],
+        };
+
+        let mut device = device();
+        config.stage(&mut device).unwrap();
+        assert_eq!(device.profiles[0].resolutions[0].dpi, Dpi::Unified(1600));
+        assert_eq!(device.profiles[0].buttons[0].action, ButtonAction::Key(lookup_key("KEY_VOLUMEUP").unwrap()));
+    }
+
+    #[test]
+    fn choosing_a_disabled_stage_enables_it() {
+        let mut profiles = device().profiles;
+        let mut second = profiles[0].resolutions[0].clone();
+        second.index = 1;
+        second.is_active = false;
+        second.is_default = false;
+        second.is_disabled = true;
+        profiles[0].resolutions.push(second);
+
+        for default in [false, true] {
+            let mut device = MouseDevice::new("Test Mouse".into(), path("/test"), profiles.clone());
+            if default {
+                device.set_default_resolution(0, 1);
+            } else {
+                device.set_active_resolution(0, 1);
+            }
+            assert!(!device.profiles[0].resolutions[1].is_disabled);
+            // The stage is enabled on the mouse before it is chosen
+            let changes: Vec<Change> = device.pending_changes().into_iter().map(|edit| edit.after).collect();
+            let enable = Change::ResolutionDisabled { profile: 0, resolution: 1, disabled: false };
+            let choose = if default {
+                Change::DefaultResolution { profile: 0, resolution: 1 }
+            } else {
+                Change::ActiveResolution { profile: 0, resolution: 1 }
+            };
+            let position = |change: &Change| changes.iter().position(|c| c == change).unwrap();
+            assert!(position(&enable) < position(&choose), "{:?}", changes);
+        }
+    }
+
+    #[test]
+    fn stage_leaves_unknown_special_alone() {
+        let config = |index| DeviceConfig {
+            name: "Test Mouse".into(),
+            profiles: vec![ProfileConfig {
+                index: 0,
+                buttons: vec![ButtonConfig {
+                    index,
+                    action: format_action(&ButtonAction::Special(SPECIAL_UNKNOWN)),
+                }],
+                ..Default::default()
+            }],
+        };
+
+        // As exported, it stages nothing
+        let mut device = device();
+        config(1).stage(&mut device).unwrap();
+        assert!(device.pending_changes().is_empty());
+
+        // Anywhere else it can't be written
+        let error = config(0).stage(&mut device).unwrap_err().to_string();
+        assert!(error.contains("profile 0, button 0: the unknown special action can't be set"), "{}", error);
+    }
+}
@@ -1,13 +1,123 @@
+use super::error::{check_status, RatbagError};
 use super::proxies::*;
-use zbus::zvariant::Value;
+use crate::keys::{key_label, short_key_label};
+use zbus::zvariant::{OwnedObjectPath, Structure, Value};
 use zbus::Connection;
 
-#[derive(Debug, Clone)]
+/// One step of a button macro. Keys are Linux input keycodes.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum MacroEvent {
+    KeyPress(u32),
+    KeyRelease(u32),
+    Wait(u32),
+}
+
+impl MacroEvent {
+    /// Decode one `(type, value)` pair of ratbagd's macro array.
+    pub fn from_raw(kind: u32, value: u32) -> Option<Self> {
+        match kind {
+            1 => Some(MacroEvent::KeyPress(value)),
+            2 => Some(MacroEvent::KeyRelease(value)),
+            3 => Some(MacroEvent::Wait(value)),
+            _ => None,
+        }
+    }
+
+    pub fn to_raw(self) -> (u32, u32) {
+        match self {
+            MacroEvent::KeyPress(k) => (1, k),
+            MacroEvent::KeyRelease(k) => (2, k),
+            MacroEvent::Wait(ms) => (3, ms),
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            MacroEvent::KeyPress(k) => format!("↓ {}", key_label(*k)),
+            MacroEvent::KeyRelease(k) => format!("↑ {}", key_label(*k)),
+            MacroEvent::Wait(ms) => format!("⏱ {} ms", ms),
+        }
+    }
+
+    fn short_label(&self) -> String {
+        match self {
+            MacroEvent::KeyPress(k) => format!("↓{}", short_key_label(*k)),
+            MacroEvent::KeyRelease(k) => format!("↑{}", short_key_label(*k)),
+            MacroEvent::Wait(ms) => format!("{}ms", ms),
+        }
+    }
+}
+
+/// libratbag's special button actions. The values mirror
+/// `enum ratbag_button_action_special`, which starts at `1 << 30`.
+pub const SPECIAL_ACTIONS: &[(u32, &str)] = &[
+    (0x4000_0001, "Double Click"),
+    (0x4000_0002, "Wheel Left"),
+    (0x4000_0003, "Wheel Right"),
+    (0x4000_0004, "Wheel Up"),
+    (0x4000_0005, "Wheel Down"),
+    (0x4000_0006, "Ratchet Mode Switch"),
+    (0x4000_0007, "Resolution Cycle Up"),
+    (0x4000_0008, "Resolution Cycle Down"),
+    (0x4000_0009, "Resolution Up"),
+    (0x4000_000a, "Resolution Down"),
+    (0x4000_000b, "Resolution Alternate (Sniper)"),
+    (0x4000_000c, "Resolution Default"),
+    (0x4000_000d, "Profile Cycle Up"),
+    (0x4000_000e, "Profile Cycle Down"),
+    (0x4000_000f, "Profile Up"),
+    (0x4000_0010, "Profile Down"),
+    (0x4000_0011, "Second Mode"),
+    (0x4000_0012, "Battery Level"),
+];
+
+/// Value libratbag uses for a special action it couldn't identify.
+pub const SPECIAL_UNKNOWN: u32 = 0x4000_0000;
+
+/// The kinds of action ratbagd can map to a button, as listed in a button's
+/// `ActionTypes` property.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ActionCategory {
+    Button,
+    Special,
+    Key,
+    Macro,
+}
+
+impl ActionCategory {
+    pub const ALL: [ActionCategory; 4] = [
+        ActionCategory::Button,
+        ActionCategory::Special,
+        ActionCategory::Key,
+        ActionCategory::Macro,
+    ];
+
+    pub fn action_type(&self) -> u32 {
+        match self {
+            ActionCategory::Button => 1,
+            ActionCategory::Special => 2,
+            ActionCategory::Key => 3,
+            ActionCategory::Macro => 4,
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            ActionCategory::Button => "Buttons",
+            ActionCategory::Special => "Specials",
+            ActionCategory::Key => "Keys",
+            ActionCategory::Macro => "Macros",
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
 pub enum ButtonAction {
     None,
     Button(u32),
     Special(u32),
     Key(u32),
+    Macro(Vec<MacroEvent>),
     Unknown,
 }
 
@@ -28,182 +138,1047 @@ impl ButtonAction {
             3 => {
                 if let Value::U32(n) = v { ButtonAction::Key(*n) } else { ButtonAction::Unknown }
             }
+            4 => {
+                let Value::Array(events) = v else {
+                    return ButtonAction::Unknown;
+                };
+                let mut steps = Vec::new();
+                for event in events.iter() {
+                    let Value::Structure(s) = event else {
+                        return ButtonAction::Unknown;
+                    };
+                    let [Value::U32(kind), Value::U32(value)] = s.fields() else {
+                        return ButtonAction::Unknown;
+                    };
+                    // ratbagd pads the array with type 0 ("none") entries
+                    match MacroEvent::from_raw(*kind, *value) {
+                        Some(step) => steps.push(step),
+                        None => break,
+                    }
+                }
+                ButtonAction::Macro(steps)
+            }
             _ => ButtonAction::Unknown,
         }
     }
 
+    /// The `(action type, value)` pair for ratbagd's `Mapping` property, or
+    /// `None` if the action can't be written back.
+    pub fn to_mapping(&self) -> Option<(u32, Value<'static>)> {
+        match self {
+            ButtonAction::None => Some((0, Value::U32(0))),
+            ButtonAction::Button(n) => Some((1, Value::U32(*n))),
+            ButtonAction::Special(n) => Some((2, Value::U32(*n))),
+            ButtonAction::Key(n) => Some((3, Value::U32(*n))),
+            ButtonAction::Macro(steps) => {
+                let raw: Vec<(u32, u32)> = steps.iter().map(|s| s.to_raw()).collect();
+                Some((4, Value::from(raw)))
+            }
+            ButtonAction::Unknown => None,
+        }
+    }
+
+    /// Left or right click, without which the mouse is hard to use.
+    pub fn is_primary_click(&self) -> bool {
+        matches!(self, ButtonAction::Button(1) | ButtonAction::Button(2))
+    }
+
+    /// Editor category the action lives under. `None` (disabled) is offered
+    /// alongside plain buttons.
+    pub fn category(&self) -> Option<ActionCategory> {
+        match self {
+            ButtonAction::None | ButtonAction::Button(_) => Some(ActionCategory::Button),
+            ButtonAction::Special(_) => Some(ActionCategory::Special),
+            ButtonAction::Key(_) => Some(ActionCategory::Key),
+            ButtonAction::Macro(_) => Some(ActionCategory::Macro),
+            ButtonAction::Unknown => None,
+        }
+    }
+
     pub fn label(&self) -> String {
-    match self {
-        ButtonAction::None => "None".into(),
-        ButtonAction::Button(n) => match n {
-            1 => "Left Click".into(),
-            2 => "Right Click".into(),
-            3 => "Middle Click".into(),
-            4 => "Back".into(),
-            5 => "Forward".into(),
-            6 => "Side Left".into(),
-            7 => "Side Right".into(),
-            8 => "Side Middle".into(),
-            _ => format!("Button {}", n),
-        },
-        ButtonAction::Special(n) => format!("Special {}", n),
-        ButtonAction::Key(n) => format!("Key {}", n),
-        ButtonAction::Unknown => "Unknown".into(),
+        match self {
+            ButtonAction::None => "None".into(),
+            ButtonAction::Button(n) => match n {
+                1 => "Left Click".into(),
+                2 => "Right Click".into(),
+                3 => "Middle Click".into(),
+                4 => "Back".into(),
+                5 => "Forward".into(),
+                6 => "Side Left".into(),
+                7 => "Side Right".into(),
+                8 => "Side Middle".into(),
+                _ => format!("Button {}", n),
+            },
+            ButtonAction::Special(n) => match SPECIAL_ACTIONS.iter().find(|(v, _)| v == n) {
+                Some((_, name)) => (*name).into(),
+                None if *n == SPECIAL_UNKNOWN => "Unknown Special".into(),
+                None => format!("Special {:#x}", n),
+            },
+            ButtonAction::Key(n) => key_label(*n),
+            ButtonAction::Macro(steps) if steps.is_empty() => "Macro (empty)".into(),
+            ButtonAction::Macro(steps) => {
+                let summary: Vec<String> = steps.iter().map(|s| s.short_label()).collect();
+                format!("Macro {}", summary.join(" "))
+            }
+            ButtonAction::Unknown => "Unknown".into(),
+        }
     }
 }
-}
 
 #[derive(Debug, Clone)]
 pub struct MouseButton {
     pub index: u32,
     pub action: ButtonAction,
-    pub path: zbus::zvariant::OwnedObjectPath,
+    /// Raw ratbagd action types the button accepts.
+    pub action_types: Vec<u32>,
+    pub path: OwnedObjectPath,
+}
+
+impl MouseButton {
+    /// Whether the firmware accepts `category` on this button. A button that
+    /// reports no action types at all is assumed to accept everything.
+    pub fn supports(&self, category: ActionCategory) -> bool {
+        self.action_types.is_empty() || self.action_types.contains(&category.action_type())
+    }
+
+    /// Whether the button can be disabled (action type 0).
+    pub fn supports_none(&self) -> bool {
+        self.action_types.is_empty() || self.action_types.contains(&0)
+    }
+
+    pub fn categories(&self) -> Vec<ActionCategory> {
+        ActionCategory::ALL
+            .into_iter()
+            .filter(|&c| self.supports(c))
+            .collect()
+    }
 }
 
+/// Resolution of a DPI stage. ratbagd reports a plain `u` for most mice and
+/// a `(uu)` tuple for mice that configure X and Y independently.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Dpi {
+    Unified(u32),
+    Separate { x: u32, y: u32 },
+}
+
+impl Dpi {
+    pub fn from_value(value: &Value<'_>) -> Option<Self> {
+        let v = match value {
+            Value::Value(inner) => inner.as_ref(),
+            other => other,
+        };
+        match v {
+            Value::U32(n) => Some(Dpi::Unified(*n)),
+            Value::Structure(s) => match s.fields() {
+                [Value::U32(x), Value::U32(y)] => Some(Dpi::Separate { x: *x, y: *y }),
+                _ => None,
+            },
+            _ => None,
+        }
+    }
+
+    /// The value to hand to ratbagd's `Resolution` property setter.
+    pub fn to_value(self) -> Value<'static> {
+        let inner = match self {
+            Dpi::Unified(n) => Value::U32(n),
+            Dpi::Separate { x, y } => Value::Structure(Structure::from((x, y))),
+        };
+        Value::Value(Box::new(inner))
+    }
+
+    pub fn x(&self) -> u32 {
+        match self {
+            Dpi::Unified(n) => *n,
+            Dpi::Separate { x, .. } => *x,
+        }
+    }
+
+    pub fn y(&self) -> u32 {
+        match self {
+            Dpi::Unified(n) => *n,
+            Dpi::Separate { y, .. } => *y,
+        }
+    }
+
+    pub fn is_separate(&self) -> bool {
+        matches!(self, Dpi::Separate { .. })
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            Dpi::Unified(n) => format!("{}", n),
+            Dpi::Separate { x, y } => format!("{}x{}", x, y),
+        }
+    }
+}
+
+/// One DPI stage of a profile, as stepped through by the mouse's DPI button.
 #[derive(Debug, Clone)]
-pub struct MouseDevice {
-    pub name: String,
-    pub dpi: u32,
+pub struct MouseResolution {
+    pub index: u32,
+    pub dpi: Dpi,
     pub valid_dpis: Vec<u32>,
+    pub is_active: bool,
+    pub is_default: bool,
+    pub is_disabled: bool,
+    pub path: OwnedObjectPath,
+}
+
+impl MouseResolution {
+    async fn load(conn: &Connection, res_path: OwnedObjectPath) -> Result<Self, RatbagError> {
+        let res = ResolutionProxy::builder(conn)
+            .path(res_path.clone())?
+            .build()
+            .await?;
+
+        let raw = res.resolution().await?;
+        let index = res.index().await?;
+        let dpi = Dpi::from_value(&raw)
+            .ok_or_else(|| RatbagError::InvalidDevice(format!("Unsupported resolution format for DPI stage {}: {:?}", index, raw)))?;
+
+        Ok(MouseResolution {
+            index,
+            dpi,
+            valid_dpis: res.resolutions().await?,
+            is_active: res.is_active().await?,
+            is_default: res.is_default().await?,
+            // Not exported by ratbagd before 0.17; treat those stages as enabled.
+            is_disabled: res.is_disabled().await.unwrap_or(false),
+            path: res_path,
+        })
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum LedMode {
+    Off,
+    On,
+    Cycle,
+    Breathing,
+    Unknown(u32),
+}
+
+impl LedMode {
+    pub fn from_u32(mode: u32) -> Self {
+        match mode {
+            0 => LedMode::Off,
+            1 => LedMode::On,
+            2 => LedMode::Cycle,
+            3 => LedMode::Breathing,
+            n => LedMode::Unknown(n),
+        }
+    }
+
+    pub fn as_u32(&self) -> u32 {
+        match self {
+            LedMode::Off => 0,
+            LedMode::On => 1,
+            LedMode::Cycle => 2,
+            LedMode::Breathing => 3,
+            LedMode::Unknown(n) => *n,
+        }
+    }
+
+    pub fn label(&self) -> String {
+        match self {
+            LedMode::Off => "Off".into(),
+            LedMode::On => "On".into(),
+            LedMode::Cycle => "Cycle".into(),
+            LedMode::Breathing => "Breathing".into(),
+            LedMode::Unknown(n) => format!("Mode {}", n),
+        }
+    }
+
+    /// Whether the mode animates, i.e. whether effect duration means anything.
+    pub fn is_animated(&self) -> bool {
+        matches!(self, LedMode::Cycle | LedMode::Breathing)
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MouseLed {
+    pub index: u32,
+    pub mode: LedMode,
+    pub modes: Vec<LedMode>,
+    pub color: (u32, u32, u32),
+    pub color_depth: u32,
+    pub brightness: u32,
+    pub effect_duration: u32,
+    pub path: OwnedObjectPath,
+}
+
+impl MouseLed {
+    pub const MAX_BRIGHTNESS: u32 = 255;
+    pub const MAX_EFFECT_DURATION: u32 = 10000;
+
+    /// ratbagd reports colour depth 0 for LEDs that only have one colour.
+    pub fn is_monochrome(&self) -> bool {
+        self.color_depth == 0
+    }
+
+    pub fn color_depth_label(&self) -> &'static str {
+        match self.color_depth {
+            0 => "monochrome",
+            1 => "RGB 888",
+            2 => "RGB 111",
+            _ => "unknown depth",
+        }
+    }
+
+    /// Reduce `color` to what the LED can actually display.
+    ///
+    /// Monochrome LEDs keep only the luminance, RGB 111 LEDs have each
+    /// channel either fully on or off.
+    pub fn quantize_color(&self, (r, g, b): (u32, u32, u32)) -> (u32, u32, u32) {
+        let (r, g, b) = (r.min(255), g.min(255), b.min(255));
+        match self.color_depth {
+            0 => {
+                let l = (r * 299 + g * 587 + b * 114) / 1000;
+                (l, l, l)
+            }
+            2 => {
+                let bit = |c: u32| if c >= 128 { 255 } else { 0 };
+                (bit(r), bit(g), bit(b))
+            }
+            _ => (r, g, b),
+        }
+    }
+
+    async fn load(conn: &Connection, led_path: OwnedObjectPath) -> Result<Self, RatbagError> {
+        let led = LedProxy::builder(conn)
+            .path(led_path.clone())?
+            .build()
+            .await?;
+
+        Ok(MouseLed {
+            index: led.index().await?,
+            mode: LedMode::from_u32(led.mode().await?),
+            modes: led.modes().await?.into_iter().map(LedMode::from_u32).collect(),
+            color: led.color().await?,
+            color_depth: led.color_depth().await?,
+            brightness: led.brightness().await?,
+            effect_duration: led.effect_duration().await?,
+            path: led_path,
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct MouseProfile {
+    pub index: u32,
+    pub name: String,
+    pub is_active: bool,
+    pub resolutions: Vec<MouseResolution>,
+    pub report_rate: u32,
+    pub report_rates: Vec<u32>,
+    /// `None` when the device can't toggle angle snapping.
+    pub angle_snapping: Option<bool>,
+    /// `None` when the device has no configurable debounce time.
+    pub debounce: Option<u32>,
+    pub debounces: Vec<u32>,
     pub buttons: Vec<MouseButton>,
-    pub device_path: zbus::zvariant::OwnedObjectPath,
-    pub resolution_path: zbus::zvariant::OwnedObjectPath,
+    pub leds: Vec<MouseLed>,
+    pub path: OwnedObjectPath,
+}
+
+impl MouseProfile {
+    async fn load(conn: &Connection, profile_path: OwnedObjectPath) -> Result<Self, RatbagError> {
+        let profile = ProfileProxy::builder(conn)
+            .path(profile_path.clone())?
+            .build()
+            .await?;
+
+        let index = profile.index().await?;
+        let name = profile.name().await?;
+        let is_active = profile.is_active().await?;
+
+        // Resolutions
+        let mut resolutions = Vec::new();
+        for res_path in profile.resolutions().await? {
+            resolutions.push(MouseResolution::load(conn, res_path).await?);
+        }
+        if resolutions.is_empty() {
+            return Err(RatbagError::InvalidDevice(format!("Profile {} has no resolutions", index)));
+        }
+
+        // Report rate
+        let report_rate = profile.report_rate().await?;
+        let report_rates = profile.report_rates().await?;
+
+        // Sensor. Older ratbagd versions lack these properties entirely, so a
+        // failed read is treated the same as ratbagd's -1 "unsupported".
+        let angle_snapping = match profile.angle_snapping().await.unwrap_or(-1) {
+            -1 => None,
+            v => Some(v != 0),
+        };
+        let debounces = profile.debounces().await.unwrap_or_default();
+        let debounce = match profile.debounce().await.unwrap_or(-1) {
+            v if v < 0 || debounces.is_empty() => None,
+            v => Some(v as u32),
+        };
+
+        // Buttons
+        let mut buttons = Vec::new();
+        for button_path in profile.buttons().await? {
+            let btn = ButtonProxy::builder(conn)
+                .path(button_path.clone())?
+                .build()
+                .await?;
+
+            let index = btn.index().await?;
+            let (action_type, raw_value) = btn.mapping().await?;
+            let action = ButtonAction::from_mapping(action_type, &raw_value);
+            let action_types = btn.action_types().await?;
+
+            buttons.push(MouseButton {
+                index,
+                action,
+                action_types,
+                path: button_path,
+            });
+        }
+
+        // LEDs
+        let mut leds = Vec::new();
+        for led_path in profile.leds().await? {
+            leds.push(MouseLed::load(conn, led_path).await?);
+        }
+
+        Ok(MouseProfile {
+            index,
+            name,
+            is_active,
+            resolutions,
+            report_rate,
+            report_rates,
+            angle_snapping,
+            debounce,
+            debounces,
+            buttons,
+            leds,
+            path: profile_path,
+        })
+    }
+
+    /// Index into `resolutions` of the stage the profile is currently using.
+    pub fn active_resolution(&self) -> usize {
+        self.resolutions.iter().position(|r| r.is_active).unwrap_or(0)
+    }
+
+    pub fn dpi(&self) -> Dpi {
+        self.resolutions[self.active_resolution()].dpi
+    }
+
+    pub fn has_sensor_settings(&self) -> bool {
+        self.angle_snapping.is_some() || self.debounce.is_some()
+    }
+
+    pub fn label(&self) -> String {
+        if self.name.is_empty() {
+            format!("Profile {}", self.index)
+        } else {
+            self.name.clone()
+        }
+    }
+}
+
+/// One setting of a device, as an assignment that can be staged or written.
+/// Profile, resolution, button and LED fields are indices into the device's
+/// vectors.
+#[derive(Debug, Clone, PartialEq)]
+pub enum Change {
+    ActiveProfile(usize),
+    Dpi { profile: usize, resolution: usize, dpi: Dpi },
+    ActiveResolution { profile: usize, resolution: usize },
+    DefaultResolution { profile: usize, resolution: usize },
+    ResolutionDisabled { profile: usize, resolution: usize, disabled: bool },
+    ReportRate { profile: usize, rate: u32 },
+    AngleSnapping { profile: usize, enabled: bool },
+    Debounce { profile: usize, debounce: u32 },
+    Button { profile: usize, button: usize, action: ButtonAction },
+    LedMode { profile: usize, led: usize, mode: LedMode },
+    LedBrightness { profile: usize, led: usize, brightness: u32 },
+    LedEffectDuration { profile: usize, led: usize, duration: u32 },
+    LedColor { profile: usize, led: usize, color: (u32, u32, u32) },
+}
+
+impl Change {
+    /// Profile the change belongs to; `None` for switching the active profile.
+    pub fn profile(&self) -> Option<usize> {
+        match *self {
+            Change::ActiveProfile(_) => None,
+            Change::Dpi { profile, .. }
+            | Change::ActiveResolution { profile, .. }
+            | Change::DefaultResolution { profile, .. }
+            | Change::ResolutionDisabled { profile, .. }
+            | Change::ReportRate { profile, .. }
+            | Change::AngleSnapping { profile, .. }
+            | Change::Debounce { profile, .. }
+            | Change::Button { profile, .. }
+            | Change::LedMode { profile, .. }
+            | Change::LedBrightness { profile, .. }
+            | Change::LedEffectDuration { profile, .. }
+            | Change::LedColor { profile, .. } => Some(profile),
+        }
+    }
+
+    /// What the change applies to, e.g. `Profile 0 · DPI stage 1`.
+    pub fn target(&self) -> String {
+        match self {
+            Change::ActiveProfile(_) => "Active profile".to_string(),
+            Change::Dpi { profile, resolution, .. } => format!("Profile {} · DPI stage {}", profile, resolution),
+            Change::ActiveResolution { profile, .. } => format!("Profile {} · active DPI stage", profile),
+            Change::DefaultResolution { profile, .. } => format!("Profile {} · default DPI stage", profile),
+            Change::ResolutionDisabled { profile, resolution, .. } => {
+                format!("Profile {} · DPI stage {} enabled", profile, resolution)
+            }
+            Change::ReportRate { profile, .. } => format!("Profile {} · report rate", profile),
+            Change::AngleSnapping { profile, .. } => format!("Profile {} · angle snapping", profile),
+            Change::Debounce { profile, .. } => format!("Profile {} · debounce", profile),
+            Change::Button { profile, button, .. } => format!("Profile {} · button {}", profile, button),
+            Change::LedMode { profile, led, .. } => format!("Profile {} · LED {} mode", profile, led),
+            Change::LedBrightness { profile, led, .. } => format!("Profile {} · LED {} brightness", profile, led),
+            Change::LedEffectDuration { profile, led, .. } => {
+                format!("Profile {} · LED {} effect duration", profile, led)
+            }
+            Change::LedColor { profile, led, .. } => format!("Profile {} · LED {} colour", profile, led),
+        }
+    }
+
+    /// The value being assigned, e.g. `800 dpi`.
+    pub fn value_label(&self) -> String {
+        match self {
+            Change::ActiveProfile(profile) => profile.to_string(),
+            Change::Dpi { dpi, .. } => format!("{} dpi", dpi.label()),
+            Change::ActiveResolution { resolution, .. } | Change::DefaultResolution { resolution, .. } => {
+                resolution.to_string()
+            }
+            Change::ResolutionDisabled { disabled, .. } => if *disabled { "no" } else { "yes" }.to_string(),
+            Change::ReportRate { rate, .. } => format!("{} Hz", rate),
+            Change::AngleSnapping { enabled, .. } => if *enabled { "on" } else { "off" }.to_string(),
+            Change::Debounce { debounce, .. } => format!("{} ms", debounce),
+            Change::Button { action, .. } => action.label(),
+            Change::LedMode { mode, .. } => mode.label(),
+            Change::LedBrightness { brightness, .. } => brightness.to_string(),
+            Change::LedEffectDuration { duration, .. } => format!("{} ms", duration),
+            Change::LedColor { color: (r, g, b), .. } => format!("#{:02x}{:02x}{:02x}", r, g, b),
+        }
+    }
+}
+
+/// A change to one setting: its value before and after.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Edit {
+    pub before: Change,
+    pub after: Change,
+}
+
+impl Edit {
+    /// `Profile 0 · DPI stage 1: 800 dpi → 1200 dpi`
+    pub fn label(&self) -> String {
+        format!(
+            "{}: {} → {}",
+            self.after.target(),
+            self.before.value_label(),
+            self.after.value_label()
+        )
+    }
+
+    /// Whether the edit remaps one of the primary buttons, or a button that
+    /// was or becomes a left or right click. Getting one of these wrong, such
+    /// as removing the last left click, can leave the mouse unusable.
+    pub fn touches_primary_button(&self) -> bool {
+        match (&self.before, &self.after) {
+            (Change::Button { button, action: before, .. }, Change::Button { action: after, .. }) => {
+                *button < 2 || before.is_primary_click() || after.is_primary_click()
+            }
+            _ => false,
+        }
+    }
+}
+
+/// Every setting that differs between two snapshots of a device's profiles,
+/// in the order they are written.
+pub fn diff(old_profiles: &[MouseProfile], new_profiles: &[MouseProfile]) -> Vec<Edit> {
+    let mut edits = Vec::new();
+    let mut push = |before: Change, after: Change| {
+        if before != after {
+            edits.push(Edit { before, after });
+        }
+    };
+
+    let active = |profiles: &[MouseProfile]| profiles.iter().position(|p| p.is_active);
+    if let (Some(before), Some(after)) = (active(old_profiles), active(new_profiles)) {
+        push(Change::ActiveProfile(before), Change::ActiveProfile(after));
+    }
+
+    for (profile, (old, new)) in old_profiles.iter().zip(new_profiles).enumerate() {
+        // Stages are enabled before they can become active or default, and
+        // disabled only once neither is left on them
+        let resolutions = || old.resolutions.iter().zip(&new.resolutions).enumerate();
+        for (resolution, (old_res, new_res)) in resolutions() {
+            if !new_res.is_disabled {
+                push(
+                    Change::ResolutionDisabled { profile, resolution, disabled: old_res.is_disabled },
+                    Change::ResolutionDisabled { profile, resolution, disabled: false },
+                );
+            }
+            push(
+                Change::Dpi { profile, resolution, dpi: old_res.dpi },
+                Change::Dpi { profile, resolution, dpi: new_res.dpi },
+            );
+        }
+        let active = |p: &MouseProfile| p.resolutions.iter().position(|r| r.is_active);
+        if let (Some(before), Some(after)) = (active(old), active(new)) {
+            push(
+                Change::ActiveResolution { profile, resolution: before },
+                Change::ActiveResolution { profile, resolution: after },
+            );
+        }
+        let default = |p: &MouseProfile| p.resolutions.iter().position(|r| r.is_default);
+        if let (Some(before), Some(after)) = (default(old), default(new)) {
+            push(
+                Change::DefaultResolution { profile, resolution: before },
+                Change::DefaultResolution { profile, resolution: after },
+            );
+        }
+        for (resolution, (old_res, new_res)) in resolutions() {
+            if new_res.is_disabled {
+                push(
+                    Change::ResolutionDisabled { profile, resolution, disabled: old_res.is_disabled },
+                    Change::ResolutionDisabled { profile, resolution, disabled: true },
+                );
+            }
+        }
+
+        push(
+            Change::ReportRate { profile, rate: old.report_rate },
+            Change::ReportRate { profile, rate: new.report_rate },
+        );
+        if let (Some(before), Some(after)) = (old.angle_snapping, new.angle_snapping) {
+            push(
+                Change::AngleSnapping { profile, enabled: before },
+                Change::AngleSnapping { profile, enabled: after },
+            );
+        }
+        if let (Some(before), Some(after)) = (old.debounce, new.debounce) {
+            push(
+                Change::Debounce { profile, debounce: before },
+                Change::Debounce { profile, debounce: after },
+            );
+        }
+
+        for (button, (old_btn, new_btn)) in old.buttons.iter().zip(&new.buttons).enumerate() {
+            push(
+                Change::Button { profile, button, action: old_btn.action.clone() },
+                Change::Button { profile, button, action: new_btn.action.clone() },
+            );
+        }
+
+        for (led, (old_led, new_led)) in old.leds.iter().zip(&new.leds).enumerate() {
+            push(
+                Change::LedMode { profile, led, mode: old_led.mode },
+                Change::LedMode { profile, led, mode: new_led.mode },
+            );
+            push(
+                Change::LedColor { profile, led, color: old_led.color },
+                Change::LedColor { profile, led, color: new_led.color },
+            );
+            push(
+                Change::LedBrightness { profile, led, brightness: old_led.brightness },
+                Change::LedBrightness { profile, led, brightness: new_led.brightness },
+            );
+            push(
+                Change::LedEffectDuration { profile, led, duration: old_led.effect_duration },
+                Change::LedEffectDuration { profile, led, duration: new_led.effect_duration },
+            );
+        }
+    }
+
+    edits
+}
+
+#[derive(Debug, Clone)]
+pub struct MouseDevice {
+    pub name: String,
+    /// Settings as shown and edited, including staged changes.
+    pub profiles: Vec<MouseProfile>,
+    pub device_path: OwnedObjectPath,
+    /// Settings as last read from or written to the device.
+    saved: Vec<MouseProfile>,
 }
 
 impl MouseDevice {
-    pub async fn load(conn: &Connection) -> Result<Vec<Self>, Box<dyn std::error::Error>> {
+    /// A device as read from ratbagd, with nothing staged.
+    pub fn new(name: String, device_path: OwnedObjectPath, profiles: Vec<MouseProfile>) -> Self {
+        MouseDevice {
+            name,
+            saved: profiles.clone(),
+            profiles,
+            device_path,
+        }
+    }
+
+    pub async fn load(conn: &Connection) -> Result<Vec<Self>, RatbagError> {
         let manager = ManagerProxy::new(conn).await?;
         let device_paths = manager.devices().await?;
         let mut devices = Vec::new();
 
         for device_path in device_paths {
-            let device = DeviceProxy::builder(conn)
-                .path(device_path.clone())?
-                .build()
-                .await?;
+            match MouseDevice::load_one(conn, device_path).await {
+                Ok(device) => devices.push(device),
+                // Unplugged while it was being read
+                Err(RatbagError::Disconnected) => {}
+                Err(e) => return Err(e),
+            }
+        }
 
-            let name = device.name().await?;
+        Ok(devices)
+    }
 
-            for profile_path in device.profiles().await? {
-                let profile = ProfileProxy::builder(conn)
-                    .path(profile_path.clone())?
-                    .build()
-                    .await?;
+    /// Read one device. Profiles ratbagtui can't show, such as ones without
+    /// DPI stages, are left out rather than failing the whole device.
+    async fn load_one(conn: &Connection, device_path: OwnedObjectPath) -> Result<Self, RatbagError> {
+        let device = DeviceProxy::builder(conn)
+            .path(device_path.clone())?
+            .build()
+            .await?;
 
-                if !profile.is_active().await? {
-                    continue;
-                }
+        let name = device.name().await?;
 
-                // Resolution
-                let mut dpi = 0u32;
-                let mut valid_dpis = Vec::new();
-                let mut resolution_path = profile.resolutions().await?.into_iter().next().unwrap();
-
-                for res_path in profile.resolutions().await? {
-                    let res = ResolutionProxy::builder(conn)
-                        .path(res_path.clone())?
-                        .build()
-                        .await?;
-
-                    if res.is_active().await? {
-                        let raw = res.resolution().await?;
-                        dpi = match &*raw {
-                            Value::Value(inner) => match &**inner {
-                                Value::U32(v) => *v,
-                                _ => 0,
-                            },
-                            Value::U32(v) => *v,
-                            _ => 0,
-                        };
-                        valid_dpis = res.resolutions().await?;
-                        resolution_path = res_path;
-                    }
-                }
+        let mut profiles = Vec::new();
+        for profile_path in device.profiles().await? {
+            match MouseProfile::load(conn, profile_path).await {
+                Ok(profile) => profiles.push(profile),
+                Err(RatbagError::InvalidDevice(_)) => {}
+                Err(e) => return Err(e),
+            }
+        }
 
-                // Buttons
-                let mut buttons = Vec::new();
-                for button_path in profile.buttons().await? {
-                    let btn = ButtonProxy::builder(conn)
-                        .path(button_path.clone())?
-                        .build()
-                        .await?;
-
-                    let index = btn.index().await?;
-                    let (action_type, raw_value) = btn.mapping().await?;
-                    let action = ButtonAction::from_mapping(action_type, &raw_value);
-
-                    buttons.push(MouseButton {
-                        index,
-                        action,
-                        path: button_path,
-                    });
-                }
+        Ok(MouseDevice::new(name, device_path, profiles))
+    }
+
+    /// Index into `profiles` of the profile the device is currently using.
+    pub fn active_profile(&self) -> Option<usize> {
+        self.profiles.iter().position(|p| p.is_active)
+    }
+
+    /// Every setting whose staged value differs from the device, in the
+    /// order they are written.
+    pub fn pending_changes(&self) -> Vec<Edit> {
+        diff(&self.saved, &self.profiles)
+    }
+
+    /// Whether anything in the profile has a staged change, including it
+    /// becoming (or ceasing to be) the active profile.
+    pub fn profile_modified(&self, profile: usize) -> bool {
+        self.pending_changes().iter().any(|c| match c.after {
+            Change::ActiveProfile(_) => self.saved[profile].is_active != self.profiles[profile].is_active,
+            _ => c.after.profile() == Some(profile),
+        })
+    }
+
+    pub fn resolution_modified(&self, profile: usize, resolution: usize) -> bool {
+        let (old, new) = (&self.saved[profile].resolutions[resolution], &self.profiles[profile].resolutions[resolution]);
+        old.dpi != new.dpi
+            || old.is_active != new.is_active
+            || old.is_default != new.is_default
+            || old.is_disabled != new.is_disabled
+    }
+
+    pub fn report_rate_modified(&self, profile: usize) -> bool {
+        self.saved[profile].report_rate != self.profiles[profile].report_rate
+    }
+
+    pub fn angle_snapping_modified(&self, profile: usize) -> bool {
+        self.saved[profile].angle_snapping != self.profiles[profile].angle_snapping
+    }
+
+    pub fn debounce_modified(&self, profile: usize) -> bool {
+        self.saved[profile].debounce != self.profiles[profile].debounce
+    }
+
+    pub fn button_modified(&self, profile: usize, button: usize) -> bool {
+        self.saved[profile].buttons[button].action != self.profiles[profile].buttons[button].action
+    }
 
-                devices.push(MouseDevice {
-                    name: name.clone(),
-                    dpi,
-                    valid_dpis,
-                    buttons,
-                    device_path: device_path.clone(),
-                    resolution_path,
-                });
+    pub fn led_modified(&self, profile: usize, led: usize) -> bool {
+        let (old, new) = (&self.saved[profile].leds[led], &self.profiles[profile].leds[led]);
+        old.mode != new.mode
+            || old.color != new.color
+            || old.brightness != new.brightness
+            || old.effect_duration != new.effect_duration
+    }
+
+    /// Whether both have the same profiles, DPI stages, buttons and LEDs, so
+    /// indices into one are valid for the other.
+    pub fn same_layout(&self, other: &MouseDevice) -> bool {
+        self.profiles.len() == other.profiles.len()
+            && self.profiles.iter().zip(&other.profiles).all(|(a, b)| {
+                a.resolutions.len() == b.resolutions.len()
+                    && a.buttons.len() == b.buttons.len()
+                    && a.leds.len() == b.leds.len()
+            })
+    }
+
+    /// Take on `fresh`, the same device as just read from ratbagd, and stage
+    /// the pending changes again on top of it. They are dropped if the layout
+    /// changed. Returns what changed on the device since it was last read or
+    /// written, i.e. changes made by something other than ratbagtui.
+    pub fn sync(&mut self, fresh: MouseDevice) -> Vec<Edit> {
+        let external = diff(&self.saved, &fresh.profiles);
+        let pending = self.pending_changes();
+        let keep = self.same_layout(&fresh);
+        *self = fresh;
+        if keep {
+            for edit in &pending {
+                self.stage(&edit.after);
             }
         }
+        external
+    }
 
-        Ok(devices)
+    /// Drop every staged change.
+    pub fn discard_changes(&mut self) {
+        self.profiles = self.saved.clone();
     }
 
-pub async fn set_dpi(&mut self, conn: &Connection, dpi: u32) -> Result<(), Box<dyn std::error::Error>> {
-    let res = ResolutionProxy::builder(conn)
-        .path(self.resolution_path.clone())?
-        .build()
-        .await?;
-
-    res.set_resolution(
-        zbus::zvariant::Value::Value(Box::new(zbus::zvariant::Value::U32(dpi).try_into().unwrap()))
-    ).await?;
-
-    let device = DeviceProxy::builder(conn)
-        .path(self.device_path.clone())?
-        .build()
-        .await?;
-
-    device.commit().await?;
-    self.dpi = dpi;
-
-    Ok(())
-}
-pub async fn set_button(
-    &mut self,
-    conn: &Connection,
-    button_index: usize,
-    action: ButtonAction,
-) -> Result<(), Box<dyn std::error::Error>> {
-    let btn_path = self.buttons[button_index].path.clone();
-
-    let btn = ButtonProxy::builder(conn)
-        .path(btn_path)?
-        .build()
-        .await?;
-
-    let (action_type, value) = match &action {
-        ButtonAction::None => (0u32, zbus::zvariant::Value::U32(0)),
-        ButtonAction::Button(n) => (1u32, zbus::zvariant::Value::U32(*n)),
-        ButtonAction::Special(n) => (2u32, zbus::zvariant::Value::U32(*n)),
-        ButtonAction::Key(n) => (3u32, zbus::zvariant::Value::U32(*n)),
-        ButtonAction::Unknown => return Ok(()),
-    };
+    /// Stage a change through the matching setter.
+    pub fn stage(&mut self, change: &Change) {
+        match *change {
+            Change::ActiveProfile(profile) => self.set_active_profile(profile),
+            Change::Dpi { profile, resolution, dpi } => self.set_dpi(profile, resolution, dpi),
+            Change::ActiveResolution { profile, resolution } => self.set_active_resolution(profile, resolution),
+            Change::DefaultResolution { profile, resolution } => self.set_default_resolution(profile, resolution),
+            Change::ResolutionDisabled { profile, resolution, disabled } => {
+                self.set_resolution_disabled(profile, resolution, disabled)
+            }
+            Change::ReportRate { profile, rate } => self.set_report_rate(profile, rate),
+            Change::AngleSnapping { profile, enabled } => self.set_angle_snapping(profile, enabled),
+            Change::Debounce { profile, debounce } => self.set_debounce(profile, debounce),
+            Change::Button { profile, button, ref action } => self.set_button(profile, button, action.clone()),
+            Change::LedMode { profile, led, mode } => self.set_led_mode(profile, led, mode),
+            Change::LedBrightness { profile, led, brightness } => self.set_led_brightness(profile, led, brightness),
+            Change::LedEffectDuration { profile, led, duration } => {
+                self.set_led_effect_duration(profile, led, duration)
+            }
+            Change::LedColor { profile, led, color } => self.set_led_color(profile, led, color),
+        }
+    }
+
+    /// Write every staged change to ratbagd and commit them to the mouse in
+    /// one go. Returns how many settings were written.
+    pub async fn write(&mut self, conn: &Connection) -> Result<usize, RatbagError> {
+        let changes = self.pending_changes();
+        if changes.is_empty() {
+            return Ok(0);
+        }
+
+        for change in &changes {
+            self.write_change(conn, &change.after).await?;
+        }
+        self.commit(conn).await?;
+        self.saved = self.profiles.clone();
+
+        Ok(changes.len())
+    }
+
+    async fn commit(&self, conn: &Connection) -> Result<(), RatbagError> {
+        let device = DeviceProxy::builder(conn)
+            .path(self.device_path.clone())?
+            .build()
+            .await?;
+
+        // ratbagd answers 0, or a libratbag error code if the mouse refused
+        match device.commit().await? {
+            0 => Ok(()),
+            code => Err(RatbagError::CommitFailed(code)),
+        }
+    }
+
+    async fn profile_proxy(
+        &self,
+        conn: &Connection,
+        profile_index: usize,
+    ) -> Result<ProfileProxy<'static>, RatbagError> {
+        let profile = ProfileProxy::builder(conn)
+            .path(self.profiles[profile_index].path.clone())?
+            .build()
+            .await?;
+        Ok(profile)
+    }
+
+    async fn resolution_proxy(
+        &self,
+        conn: &Connection,
+        profile_index: usize,
+        resolution_index: usize,
+    ) -> Result<ResolutionProxy<'static>, RatbagError> {
+        let res = ResolutionProxy::builder(conn)
+            .path(self.profiles[profile_index].resolutions[resolution_index].path.clone())?
+            .build()
+            .await?;
+        Ok(res)
+    }
+
+    async fn button_proxy(
+        &self,
+        conn: &Connection,
+        profile_index: usize,
+        button_index: usize,
+    ) -> Result<ButtonProxy<'static>, RatbagError> {
+        let btn = ButtonProxy::builder(conn)
+            .path(self.profiles[profile_index].buttons[button_index].path.clone())?
+            .build()
+            .await?;
+        Ok(btn)
+    }
+
+    async fn led_proxy(
+        &self,
+        conn: &Connection,
+        profile_index: usize,
+        led_index: usize,
+    ) -> Result<LedProxy<'static>, RatbagError> {
+        let led = LedProxy::builder(conn)
+            .path(self.profiles[profile_index].leds[led_index].path.clone())?
+            .build()
+            .await?;
+        Ok(led)
+    }
+
+    /// Send one setting to ratbagd, without committing it to the mouse.
+    async fn write_change(&self, conn: &Connection, change: &Change) -> Result<(), RatbagError> {
+        match *change {
+            Change::ActiveProfile(profile) => {
+                let code = self.profile_proxy(conn, profile).await?.set_active().await?;
+                check_status(code, || format!("make profile {} active", self.profiles[profile].index))?;
+            }
+            Change::Dpi { profile, resolution, dpi } => {
+                let res = self.resolution_proxy(conn, profile, resolution).await?;
+                res.set_resolution(dpi.to_value()).await?;
+            }
+            Change::ActiveResolution { profile, resolution } => {
+                let code = self.resolution_proxy(conn, profile, resolution).await?.set_active().await?;
+                let index = self.profiles[profile].resolutions[resolution].index;
+                check_status(code, || format!("make DPI stage {} active", index))?;
+            }
+            Change::DefaultResolution { profile, resolution } => {
+                let code = self.resolution_proxy(conn, profile, resolution).await?.set_default().await?;
+                let index = self.profiles[profile].resolutions[resolution].index;
+                check_status(code, || format!("make DPI stage {} the default", index))?;
+            }
+            Change::ResolutionDisabled { profile, resolution, disabled } => {
+                let res = self.resolution_proxy(conn, profile, resolution).await?;
+                res.set_is_disabled(disabled).await?;
+            }
+            Change::ReportRate { profile, rate } => {
+                self.profile_proxy(conn, profile).await?.set_report_rate(rate).await?;
+            }
+            Change::AngleSnapping { profile, enabled } => {
+                let proxy = self.profile_proxy(conn, profile).await?;
+                proxy.set_angle_snapping(enabled as i32).await?;
+            }
+            Change::Debounce { profile, debounce } => {
+                self.profile_proxy(conn, profile).await?.set_debounce(debounce as i32).await?;
+            }
+            Change::Button { profile, button, ref action } => {
+                let target = &self.profiles[profile].buttons[button];
+                let supported = match action {
+                    ButtonAction::None => target.supports_none(),
+                    _ => action.category().is_some_and(|c| target.supports(c)),
+                };
+                let Some(mapping) = action.to_mapping().filter(|_| supported) else {
+                    return Err(RatbagError::UnsupportedAction {
+                        button: target.index,
+                        action: action.label(),
+                    });
+                };
+                self.button_proxy(conn, profile, button).await?.set_mapping(mapping).await?;
+            }
+            Change::LedMode { profile, led, mode } => {
+                self.led_proxy(conn, profile, led).await?.set_mode(mode.as_u32()).await?;
+            }
+            Change::LedBrightness { profile, led, brightness } => {
+                self.led_proxy(conn, profile, led).await?.set_brightness(brightness).await?;
+            }
+            Change::LedEffectDuration { profile, led, duration } => {
+                self.led_proxy(conn, profile, led).await?.set_effect_duration(duration).await?;
+            }
+            Change::LedColor { profile, led, color } => {
+                self.led_proxy(conn, profile, led).await?.set_color(color).await?;
+            }
+        }
+        Ok(())
+    }
+
+    // The setters below only stage a change; nothing reaches the mouse until
+    // `write` is called.
 
-    btn.set_mapping((action_type, value)).await?;
+    pub fn set_active_profile(&mut self, profile_index: usize) {
+        for (i, p) in self.profiles.iter_mut().enumerate() {
+            p.is_active = i == profile_index;
+        }
+    }
 
-    let device = DeviceProxy::builder(conn)
-        .path(self.device_path.clone())?
-        .build()
-        .await?;
+    pub fn set_dpi(&mut self, profile_index: usize, resolution_index: usize, dpi: Dpi) {
+        self.profiles[profile_index].resolutions[resolution_index].dpi = dpi;
+    }
 
-    device.commit().await?;
-    self.buttons[button_index].action = action;
+    /// Make a DPI stage the active one, enabling it if it was disabled.
+    pub fn set_active_resolution(&mut self, profile_index: usize, resolution_index: usize) {
+        for (i, r) in self.profiles[profile_index].resolutions.iter_mut().enumerate() {
+            r.is_active = i == resolution_index;
+        }
+        self.profiles[profile_index].resolutions[resolution_index].is_disabled = false;
+    }
 
-    Ok(())
+    /// Make a DPI stage the default one, enabling it if it was disabled.
+    pub fn set_default_resolution(&mut self, profile_index: usize, resolution_index: usize) {
+        for (i, r) in self.profiles[profile_index].resolutions.iter_mut().enumerate() {
+            r.is_default = i == resolution_index;
+        }
+        self.profiles[profile_index].resolutions[resolution_index].is_disabled = false;
+    }
+
+    pub fn set_resolution_disabled(&mut self, profile_index: usize, resolution_index: usize, disabled: bool) {
+        self.profiles[profile_index].resolutions[resolution_index].is_disabled = disabled;
+    }
+
+    pub fn set_report_rate(&mut self, profile_index: usize, rate: u32) {
+        self.profiles[profile_index].report_rate = rate;
+    }
+
+    pub fn set_angle_snapping(&mut self, profile_index: usize, enabled: bool) {
+        self.profiles[profile_index].angle_snapping = Some(enabled);
+    }
+
+    pub fn set_debounce(&mut self, profile_index: usize, debounce: u32) {
+        self.profiles[profile_index].debounce = Some(debounce);
+    }
+
+    /// Actions ratbagd can't represent (`Unknown`) are ignored.
+    pub fn set_button(&mut self, profile_index: usize, button_index: usize, action: ButtonAction) {
+        if action.to_mapping().is_some() {
+            self.profiles[profile_index].buttons[button_index].action = action;
+        }
+    }
+
+    pub fn set_led_mode(&mut self, profile_index: usize, led_index: usize, mode: LedMode) {
+        self.profiles[profile_index].leds[led_index].mode = mode;
+    }
+
+    pub fn set_led_brightness(&mut self, profile_index: usize, led_index: usize, brightness: u32) {
+        self.profiles[profile_index].leds[led_index].brightness = brightness.min(MouseLed::MAX_BRIGHTNESS);
+    }
+
+    pub fn set_led_effect_duration(&mut self, profile_index: usize, led_index: usize, duration: u32) {
+        self.profiles[profile_index].leds[led_index].effect_duration = duration.min(MouseLed::MAX_EFFECT_DURATION);
+    }
+
+    pub fn set_led_color(&mut self, profile_index: usize, led_index: usize, color: (u32, u32, u32)) {
+        let led = &mut self.profiles[profile_index].leds[led_index];
+        led.color = led.quantize_color(color);
+    }
 }
-}
\ No newline at end of file
@@ -0,0 +1,123 @@
+use std::fmt;
+use std::time::Duration;
+use zbus::DBusError;
+
+/// What went wrong talking to ratbagd or a mouse, sorted into the cases a
+/// user can do something about.
+#[derive(Debug)]
+pub enum RatbagError {
+    /// ratbagd isn't on the system bus.
+    DaemonNotRunning,
+    /// Access was refused, either to ratbagd over the bus (`node` is `None`)
+    /// or to a device node such as `/dev/hidraw6`.
+    PermissionDenied { node: Option<String> },
+    /// The device went away while it was being read or written.
+    Disconnected,
+    /// ratbagd rejected a value the device can't take.
+    OutOfRange(String),
+    /// The button can't take this kind of action.
+    UnsupportedAction { button: u32, action: String },
+    /// ratbagd refused a setting, with its error code.
+    Refused { what: String, code: u32 },
+    /// ratbagd couldn't commit the changes to the mouse, with its error code.
+    CommitFailed(u32),
+    /// ratbagd took longer than this to answer.
+    TimedOut(Duration),
+    /// ratbagd described a device in a way ratbagtui can't use.
+    InvalidDevice(String),
+    /// Any other D-Bus error.
+    Bus(zbus::Error),
+}
+
+impl RatbagError {
+    /// A short suggestion for fixing the problem, if there is one.
+    pub fn hint(&self) -> Option<&'static str> {
+        match self {
+            RatbagError::DaemonNotRunning => Some("start it with `sudo systemctl enable --now ratbagd`"),
+            RatbagError::PermissionDenied { node: None } => {
+                Some("install pkg/70-libratbag.rules into /etc/udev/rules.d/ and replug the mouse")
+            }
+            RatbagError::PermissionDenied { node: Some(_) } => {
+                Some("add yourself to the input group with `sudo usermod -aG input $USER`, then log out and back in")
+            }
+            RatbagError::Disconnected => Some("reconnect the mouse or its receiver; it is picked up again automatically"),
+            RatbagError::OutOfRange(_) => Some("run `ratbagtui info` to see the values the device supports"),
+            RatbagError::UnsupportedAction { .. } => {
+                Some("the action editor only offers the kinds of action each button takes")
+            }
+            RatbagError::Refused { .. } => Some("run `ratbagtui info` to see the values the device supports"),
+            RatbagError::CommitFailed(_) => {
+                Some("wake a wireless mouse by moving it and try again; nothing staged was lost")
+            }
+            RatbagError::TimedOut(_) => Some("give a slow wireless mouse longer with `--timeout SECS`"),
+            RatbagError::InvalidDevice(_) | RatbagError::Bus(_) => None,
+        }
+    }
+}
+
+impl fmt::Display for RatbagError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            RatbagError::DaemonNotRunning => write!(f, "ratbagd is not running"),
+            RatbagError::PermissionDenied { node: None } => write!(f, "permission denied by ratbagd"),
+            RatbagError::PermissionDenied { node: Some(node) } => write!(f, "permission denied opening {}", node),
+            RatbagError::Disconnected => write!(f, "the device is no longer connected"),
+            RatbagError::OutOfRange(detail) => write!(f, "value out of range: {}", detail),
+            RatbagError::UnsupportedAction { button, action } => {
+                write!(f, "button {} can't be mapped to {}", button, action)
+            }
+            RatbagError::Refused { what, code } => write!(f, "ratbagd refused to {} (error {})", what, code),
+            RatbagError::CommitFailed(code) => write!(f, "committing to the mouse failed (ratbagd error {})", code),
+            RatbagError::TimedOut(timeout) => write!(f, "ratbagd did not answer within {}s", timeout.as_secs()),
+            RatbagError::InvalidDevice(detail) => write!(f, "{}", detail),
+            RatbagError::Bus(e) => write!(f, "{}", e),
+        }
+    }
+}
+
+/// Turn a status code returned by a ratbagd method into an error unless it
+/// is 0, describing the call with `what`.
+pub fn check_status(code: u32, what: impl FnOnce() -> String) -> Result<(), RatbagError> {
+    match code {
+        0 => Ok(()),
+        code => Err(RatbagError::Refused { what: what(), code }),
+    }
+}
+
+impl std::error::Error for RatbagError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            RatbagError::Bus(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<zbus::Error> for RatbagError {
+    fn from(e: zbus::Error) -> Self {
+        let (name, description) = match &e {
+            zbus::Error::MethodError(name, description, _) => (name.to_string(), description.clone()),
+            zbus::Error::FDO(fdo) => (fdo.name().to_string(), fdo.description().map(str::to_string)),
+            zbus::Error::InputOutput(io) if io.kind() == std::io::ErrorKind::PermissionDenied => {
+                return RatbagError::PermissionDenied { node: None };
+            }
+            _ => return RatbagError::Bus(e),
+        };
+        // sd-bus, which ratbagd uses, turns errno values into these names
+        match name.as_str() {
+            "org.freedesktop.DBus.Error.ServiceUnknown" | "org.freedesktop.DBus.Error.NameHasNoOwner" => {
+                RatbagError::DaemonNotRunning
+            }
+            "org.freedesktop.DBus.Error.AccessDenied" | "org.freedesktop.DBus.Error.AuthFailed" => {
+                RatbagError::PermissionDenied { node: None }
+            }
+            "org.freedesktop.DBus.Error.UnknownObject" | "System.Error.ENODEV" | "System.Error.ENXIO" => {
+                RatbagError::Disconnected
+            }
+            "org.freedesktop.DBus.Error.InvalidArgs" | "System.Error.ERANGE" => {
+                RatbagError::OutOfRange(description.unwrap_or(name))
+            }
+            _ => RatbagError::Bus(e),
+        }
+    }
+}
@@ -1,2 +1,5 @@
 pub mod device;
-pub mod proxies;
\ No newline at end of file
+pub mod error;
+pub mod proxies;
+pub mod watch;
+pub mod worker;
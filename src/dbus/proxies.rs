@@ -31,14 +31,49 @@ pub trait Device {
     default_service = "org.freedesktop.ratbag1"
 )]
 pub trait Profile {
+    #[zbus(property)]
+    fn index(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn name(&self) -> zbus::Result<String>;
+
     #[zbus(property)]
     fn resolutions(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
 
     #[zbus(property)]
     fn buttons(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
 
+    #[zbus(property)]
+    fn leds(&self) -> zbus::Result<Vec<OwnedObjectPath>>;
+
+    #[zbus(property)]
+    fn report_rate(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn set_report_rate(&self, value: u32) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn report_rates(&self) -> zbus::Result<Vec<u32>>;
+
+    #[zbus(property)]
+    fn angle_snapping(&self) -> zbus::Result<i32>;
+
+    #[zbus(property)]
+    fn set_angle_snapping(&self, value: i32) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn debounce(&self) -> zbus::Result<i32>;
+
+    #[zbus(property)]
+    fn set_debounce(&self, value: i32) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn debounces(&self) -> zbus::Result<Vec<u32>>;
+
     #[zbus(property)]
     fn is_active(&self) -> zbus::Result<bool>;
+
+    fn set_active(&self) -> zbus::Result<u32>;
 }
 
 #[proxy(
@@ -46,6 +81,9 @@ pub trait Profile {
     default_service = "org.freedesktop.ratbag1"
 )]
 pub trait Resolution {
+    #[zbus(property)]
+    fn index(&self) -> zbus::Result<u32>;
+
     #[zbus(property)]
     fn resolution(&self) -> zbus::Result<OwnedValue>;
 
@@ -57,6 +95,19 @@ pub trait Resolution {
 
     #[zbus(property)]
     fn is_active(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn is_default(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn is_disabled(&self) -> zbus::Result<bool>;
+
+    #[zbus(property)]
+    fn set_is_disabled(&self, value: bool) -> zbus::Result<()>;
+
+    fn set_active(&self) -> zbus::Result<u32>;
+
+    fn set_default(&self) -> zbus::Result<u32>;
 }
 
 #[proxy(
@@ -75,4 +126,43 @@ pub trait Button {
 
     #[zbus(property)]
     fn index(&self) -> zbus::Result<u32>;
-}
\ No newline at end of file
+}
+
+#[proxy(
+    interface = "org.freedesktop.ratbag1.Led",
+    default_service = "org.freedesktop.ratbag1"
+)]
+pub trait Led {
+    #[zbus(property)]
+    fn index(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn mode(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn set_mode(&self, value: u32) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn modes(&self) -> zbus::Result<Vec<u32>>;
+
+    #[zbus(property)]
+    fn color(&self) -> zbus::Result<(u32, u32, u32)>;
+
+    #[zbus(property)]
+    fn set_color(&self, value: (u32, u32, u32)) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn color_depth(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn brightness(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn set_brightness(&self, value: u32) -> zbus::Result<()>;
+
+    #[zbus(property)]
+    fn effect_duration(&self) -> zbus::Result<u32>;
+
+    #[zbus(property)]
+    fn set_effect_duration(&self, value: u32) -> zbus::Result<()>;
+}
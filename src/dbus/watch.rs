@@ -0,0 +1,75 @@
+use futures_lite::StreamExt;
+use tokio::sync::mpsc;
+use zbus::message::Type;
+use zbus::{Connection, MatchRule, MessageStream};
+
+const SERVICE: &str = "org.freedesktop.ratbag1";
+const ROOT_PATH: &str = "/org/freedesktop/ratbag1";
+
+/// Something happened in ratbagd that the loaded devices may not reflect yet.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum DeviceEvent {
+    /// A property of the manager, a device or one of its profiles,
+    /// resolutions, buttons or LEDs changed. Devices being plugged in or
+    /// removed show up as a change to the manager's `Devices`.
+    Changed,
+    /// ratbagd re-read a device, e.g. after it was reconnected.
+    Resync,
+    /// ratbagd left the bus, e.g. for an upgrade or after a crash.
+    DaemonStopped,
+    /// ratbagd started, or came back after stopping.
+    DaemonStarted,
+}
+
+/// Send an event for every `PropertiesChanged` and `Resync` signal from
+/// ratbagd, and whenever ratbagd starts or stops, until the receiver is
+/// dropped. Signals often come in bursts, one per property, so receivers
+/// should wait for a quiet moment before reloading.
+pub async fn start_watcher(conn: &Connection, tx: mpsc::Sender<DeviceEvent>) -> zbus::Result<()> {
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .sender("org.freedesktop.DBus")?
+        .interface("org.freedesktop.DBus")?
+        .member("NameOwnerChanged")?
+        .add_arg(SERVICE)?
+        .build();
+    let mut owner_stream = MessageStream::for_match_rule(rule, conn, Some(16)).await?;
+    let owner_tx = tx.clone();
+
+    tokio::spawn(async move {
+        while let Some(Ok(message)) = owner_stream.next().await {
+            let Ok((_, _, new_owner)) = message.body().deserialize::<(String, String, String)>() else {
+                continue;
+            };
+            let event = if new_owner.is_empty() {
+                DeviceEvent::DaemonStopped
+            } else {
+                DeviceEvent::DaemonStarted
+            };
+            if owner_tx.send(event).await.is_err() {
+                return;
+            }
+        }
+    });
+
+    let rule = MatchRule::builder()
+        .msg_type(Type::Signal)
+        .sender(SERVICE)?
+        .path_namespace(ROOT_PATH)?
+        .build();
+    let mut stream = MessageStream::for_match_rule(rule, conn, Some(256)).await?;
+
+    tokio::spawn(async move {
+        while let Some(Ok(message)) = stream.next().await {
+            let event = match message.header().member().map(|m| m.as_str()) {
+                Some("PropertiesChanged") => DeviceEvent::Changed,
+                Some("Resync") => DeviceEvent::Resync,
+                _ => continue,
+            };
+            if tx.send(event).await.is_err() {
+                return;
+            }
+        }
+    });
+    Ok(())
+}
@@ -0,0 +1,77 @@
+use super::device::MouseDevice;
+use super::error::RatbagError;
+use std::time::Duration;
+use tokio::sync::mpsc;
+use zbus::Connection;
+
+/// Something for the worker to do with ratbagd.
+pub enum Job {
+    /// Read every device.
+    Load,
+    /// Write a device's staged changes and commit them.
+    Write(MouseDevice),
+}
+
+pub enum Outcome {
+    Loaded(Vec<MouseDevice>),
+    /// The device as written, with nothing left staged.
+    Written { device: MouseDevice, changes: usize },
+}
+
+struct Request {
+    id: u64,
+    job: Job,
+}
+
+/// The result of the job submitted under `id`.
+pub struct Response {
+    pub id: u64,
+    pub result: Result<Outcome, RatbagError>,
+}
+
+/// Handle for submitting jobs to the worker task.
+pub struct Worker {
+    tx: mpsc::UnboundedSender<Request>,
+    next_id: u64,
+}
+
+impl Worker {
+    /// Start the worker. Jobs run one at a time, in the order submitted, and
+    /// fail if ratbagd takes longer than `timeout` over one.
+    pub fn spawn(conn: Connection, timeout: Duration) -> (Worker, mpsc::UnboundedReceiver<Response>) {
+        let (tx, mut requests) = mpsc::unbounded_channel::<Request>();
+        let (response_tx, responses) = mpsc::unbounded_channel();
+
+        tokio::spawn(async move {
+            while let Some(Request { id, job }) = requests.recv().await {
+                let result = match tokio::time::timeout(timeout, run(&conn, job)).await {
+                    Ok(result) => result,
+                    Err(_) => Err(RatbagError::TimedOut(timeout)),
+                };
+                if response_tx.send(Response { id, result }).is_err() {
+                    return;
+                }
+            }
+        });
+
+        (Worker { tx, next_id: 0 }, responses)
+    }
+
+    /// Queue `job` and return the id its response will carry.
+    pub fn submit(&mut self, job: Job) -> u64 {
+        self.next_id += 1;
+        // The worker only stops once the handle is dropped
+        let _ = self.tx.send(Request { id: self.next_id, job });
+        self.next_id
+    }
+}
+
+async fn run(conn: &Connection, job: Job) -> Result<Outcome, RatbagError> {
+    match job {
+        Job::Load => MouseDevice::load(conn).await.map(Outcome::Loaded),
+        Job::Write(mut device) => {
+            let changes = device.write(conn).await?;
+            Ok(Outcome::Written { device, changes })
+        }
+    }
+}
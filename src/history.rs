@@ -0,0 +1,48 @@
+use crate::dbus::device::Edit;
+
+/// How many steps of undo history are kept.
+const MAX_STEPS: usize = 100;
+
+/// Undo and redo stacks of device edits. A step holds every setting changed
+/// by one action, in the order they changed.
+#[derive(Default)]
+pub struct History {
+    undo: Vec<Vec<Edit>>,
+    redo: Vec<Vec<Edit>>,
+}
+
+impl History {
+    /// Record a new step. Anything that could be redone is dropped.
+    pub fn record(&mut self, step: Vec<Edit>) {
+        if step.is_empty() {
+            return;
+        }
+        self.redo.clear();
+        self.undo.push(step);
+        if self.undo.len() > MAX_STEPS {
+            self.undo.remove(0);
+        }
+    }
+
+    /// The step to reverse, moved onto the redo stack.
+    pub fn undo(&mut self) -> Option<&[Edit]> {
+        let step = self.undo.pop()?;
+        self.redo.push(step);
+        self.redo.last().map(Vec::as_slice)
+    }
+
+    /// The step to apply again, moved back onto the undo stack.
+    pub fn redo(&mut self) -> Option<&[Edit]> {
+        let step = self.redo.pop()?;
+        self.undo.push(step);
+        self.undo.last().map(Vec::as_slice)
+    }
+}
+
+/// Status line summary of a step, e.g. `Profile 0 · button 3: Button 1 → None`.
+pub fn describe(step: &[Edit]) -> String {
+    match step {
+        [edit] => edit.label(),
+        _ => format!("{} changes", step.len()),
+    }
+}
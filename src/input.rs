@@ -1,8 +1,16 @@
-use std::fs::File;
-use std::io::Read;
+use crate::dbus::device::MacroEvent;
+use crate::keys::{CapturedKey, MODIFIER_KEYS, is_modifier};
+use evdev::{EventType, KeyCode};
+use std::fs::OpenOptions;
+use std::io::{self, Read};
+use std::os::unix::fs::OpenOptionsExt;
 use std::path::Path;
+use std::time::SystemTime;
+use tokio::io::unix::AsyncFd;
 use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
 
+#[allow(clippy::collapsible_if)]
 pub fn find_mouse_device() -> Option<String> {
     let by_id = Path::new("/dev/input/by-id");
     if let Ok(entries) = std::fs::read_dir(by_id) {
@@ -18,38 +26,148 @@ pub fn find_mouse_device() -> Option<String> {
     None
 }
 
-pub async fn start_input_listener(path: String, tx: mpsc::Sender<u16>) {
-    tokio::task::spawn_blocking(move || {
-        let mut file = match File::open(&path) {
-            Ok(f) => f,
-            Err(e) => {
-                eprintln!("Failed to open hidraw device: {}", e);
-                return;
-            }
-        };
+/// Open the hidraw node at `path` and send the number of every mouse button
+/// pressed from then on, until the receiver is dropped or the returned task
+/// is aborted.
+pub fn start_input_listener(path: &str, tx: mpsc::Sender<u16>) -> io::Result<JoinHandle<()>> {
+    // Non-blocking, so aborting the task stops the reader straight away
+    let file = OpenOptions::new().read(true).custom_flags(libc::O_NONBLOCK).open(path)?;
+    let file = AsyncFd::new(file)?;
 
+    Ok(tokio::spawn(async move {
         let mut buf = [0u8; 64];
         let mut prev_buttons = 0u8;
 
         loop {
-            match file.read(&mut buf) {
-                Ok(n) if n > 0 => {
-                    let buttons = if n > 1 { buf[1] } else { buf[0] };
-                    let pressed = buttons & !prev_buttons;
-                    prev_buttons = buttons;
-
-                    for bit in 0..8u16 {
-                        if pressed & (1 << bit) != 0 {
-                            let button_num = bit + 1;
-                            if tx.blocking_send(button_num).is_err() {
-                                return;
-                            }
-                        }
+            let Ok(mut ready) = file.readable().await else {
+                return;
+            };
+            let n = match ready.try_io(|file| file.get_ref().read(&mut buf)) {
+                Ok(Ok(n)) if n > 0 => n,
+                Ok(Ok(_)) => continue,
+                Ok(Err(_)) => return,
+                // Not actually readable yet
+                Err(_) => continue,
+            };
+            let buttons = if n > 1 { buf[1] } else { buf[0] };
+            let pressed = buttons & !prev_buttons;
+            prev_buttons = buttons;
+
+            for bit in 0..8u16 {
+                if pressed & (1 << bit) != 0 {
+                    let button_num = bit + 1;
+                    if tx.send(button_num).await.is_err() {
+                        return;
                     }
                 }
-                Ok(_) => {}
-                Err(_) => break,
             }
         }
-    });
-}
\ No newline at end of file
+    }))
+}
+
+/// Open every readable evdev node that looks like a keyboard.
+pub fn find_keyboards() -> Vec<evdev::Device> {
+    evdev::enumerate()
+        .map(|(_, device)| device)
+        .filter(|device| {
+            device.supported_keys().is_some_and(|keys| {
+                keys.contains(KeyCode::KEY_A) && keys.contains(KeyCode::KEY_Z) && keys.contains(KeyCode::KEY_ENTER)
+            })
+        })
+        .collect()
+}
+
+/// Read the next key pressed on any of `keyboards` and send it, together with
+/// the modifiers held at the time. A modifier pressed and released on its own
+/// is sent as the key itself. Each reader stops after one key, or once the
+/// receiver has been dropped.
+pub fn start_key_capture(keyboards: Vec<evdev::Device>, tx: mpsc::Sender<CapturedKey>) {
+    for device in keyboards {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let mut held: Vec<u32> = Vec::new();
+            let mut lone_modifier: Option<u32> = None;
+
+            let Ok(mut events) = device.into_event_stream() else {
+                return;
+            };
+            loop {
+                let Some(event) = next_event(&mut events, &tx).await else {
+                    return;
+                };
+                if event.event_type() != EventType::KEY {
+                    continue;
+                }
+                let code = event.code() as u32;
+                let captured = match (event.value(), is_modifier(code)) {
+                    // Press of a modifier: remember it and keep reading
+                    (1, true) => {
+                        if !held.contains(&code) {
+                            held.push(code);
+                        }
+                        lone_modifier = (held.len() == 1).then_some(code);
+                        None
+                    }
+                    (0, true) => {
+                        held.retain(|&m| m != code);
+                        lone_modifier.take().filter(|&m| m == code).map(|m| CapturedKey {
+                            code: m,
+                            modifiers: Vec::new(),
+                        })
+                    }
+                    (1, false) => {
+                        let mut modifiers = held.clone();
+                        modifiers.sort_by_key(|&m| MODIFIER_KEYS.iter().position(|k| k.code() as u32 == m));
+                        Some(CapturedKey { code, modifiers })
+                    }
+                    _ => None,
+                };
+                if let Some(captured) = captured {
+                    let _ = tx.send(captured).await;
+                    return;
+                }
+            }
+        });
+    }
+}
+
+/// Send every key press and release on any of `keyboards`, with the time it
+/// happened, until the receiver is dropped. Auto-repeat is ignored.
+pub fn start_key_recorder(keyboards: Vec<evdev::Device>, tx: mpsc::Sender<(MacroEvent, SystemTime)>) {
+    for device in keyboards {
+        let tx = tx.clone();
+        tokio::spawn(async move {
+            let Ok(mut events) = device.into_event_stream() else {
+                return;
+            };
+            while let Some(event) = next_event(&mut events, &tx).await {
+                if event.event_type() != EventType::KEY {
+                    continue;
+                }
+                // Skip mouse clicks from receivers that are keyboard and mouse in one
+                if (KeyCode::BTN_0.code()..KeyCode::KEY_OK.code()).contains(&event.code()) {
+                    continue;
+                }
+                let code = event.code() as u32;
+                let step = match event.value() {
+                    1 => MacroEvent::KeyPress(code),
+                    0 => MacroEvent::KeyRelease(code),
+                    _ => continue,
+                };
+                if tx.send((step, event.timestamp())).await.is_err() {
+                    return;
+                }
+            }
+        });
+    }
+}
+
+/// The next event from a keyboard, or `None` once it has gone away or the
+/// receiver of `tx` has been dropped, so readers stop as soon as they are no
+/// longer wanted rather than after the next key.
+async fn next_event<T>(events: &mut evdev::EventStream, tx: &mpsc::Sender<T>) -> Option<evdev::InputEvent> {
+    tokio::select! {
+        event = events.next_event() => event.ok(),
+        () = tx.closed() => None,
+    }
+}
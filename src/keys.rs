@@ -0,0 +1,457 @@
+use crossterm::event::{KeyCode as TermKey, KeyEvent, KeyModifiers, MediaKeyCode, ModifierKeyCode};
+use evdev::KeyCode;
+use ratatui::widgets::ListState;
+use std::sync::OnceLock;
+
+/// Highest keycode worth offering; everything above is reserved or BTN_*.
+const KEY_MAX: u16 = 0x2ff;
+
+/// Every `KEY_*` code the evdev crate has a name for, in keycode order.
+pub fn all_keys() -> &'static [(u32, String)] {
+    static KEYS: OnceLock<Vec<(u32, String)>> = OnceLock::new();
+    KEYS.get_or_init(|| {
+        (1..=KEY_MAX)
+            .filter_map(|code| {
+                let name = format!("{:?}", KeyCode::new(code));
+                name.starts_with("KEY_").then_some((code as u32, name))
+            })
+            .collect()
+    })
+}
+
+/// Linux input name for `code`, e.g. `KEY_VOLUMEUP`.
+pub fn key_name(code: u32) -> Option<&'static str> {
+    all_keys()
+        .iter()
+        .find(|(c, _)| *c == code)
+        .map(|(_, name)| name.as_str())
+}
+
+/// Keycode for a `KEY_*` name, with or without the prefix and in any case,
+/// or for a plain keycode number.
+pub fn lookup_key(text: &str) -> Option<u32> {
+    if let Ok(code) = text.parse() {
+        return Some(code);
+    }
+    let upper = text.to_ascii_uppercase();
+    let name = if upper.starts_with("KEY_") { upper } else { format!("KEY_{}", upper) };
+    all_keys().iter().find(|(_, n)| *n == name).map(|(code, _)| *code)
+}
+
+/// `KEY_VOLUMEUP`, or `Key 1234` for codes without a name.
+pub fn key_label(code: u32) -> String {
+    match key_name(code) {
+        Some(name) => name.to_string(),
+        None => format!("Key {}", code),
+    }
+}
+
+/// `VOLUMEUP`, or the bare number for codes without a name.
+pub fn short_key_label(code: u32) -> String {
+    match key_name(code) {
+        Some(name) => name.trim_start_matches("KEY_").to_string(),
+        None => code.to_string(),
+    }
+}
+
+/// Score `name` against `query` as a case-insensitive subsequence match.
+/// Higher is better; `None` means no match. Consecutive matches and matches
+/// at the start of the name after the `KEY_` prefix score higher.
+pub fn fuzzy_score(query: &str, name: &str) -> Option<i32> {
+    let name = name.strip_prefix("KEY_").unwrap_or(name).to_ascii_lowercase();
+    let query = query.trim().to_ascii_lowercase();
+    let query = query.strip_prefix("key_").unwrap_or(&query);
+    if query.is_empty() {
+        return Some(0);
+    }
+    if name == query {
+        return Some(1000);
+    }
+
+    let mut score = 0;
+    let mut last: Option<usize> = None;
+    let mut chars = name.char_indices();
+    for q in query.chars() {
+        let (i, _) = chars.by_ref().find(|&(_, c)| c == q)?;
+        score += match last {
+            Some(l) if l + 1 == i => 10,
+            None if i == 0 => 20,
+            _ => 1,
+        };
+        last = Some(i);
+    }
+    // Prefer shorter names, so "a" ranks KEY_A above KEY_AGAIN
+    Some(score * 10 - name.len() as i32)
+}
+
+/// Searchable list of keys for the key mapping popup.
+pub struct KeyPicker {
+    pub query: String,
+    pub matches: Vec<(u32, &'static str)>,
+    pub state: ListState,
+}
+
+impl KeyPicker {
+    pub fn new(current: Option<u32>) -> Self {
+        let mut picker = KeyPicker {
+            query: String::new(),
+            matches: Vec::new(),
+            state: ListState::default(),
+        };
+        picker.refilter();
+        if let Some(i) = current.and_then(|c| picker.matches.iter().position(|(k, _)| *k == c)) {
+            picker.state.select(Some(i));
+        }
+        picker
+    }
+
+    fn refilter(&mut self) {
+        // A bare number also matches the keycode itself
+        let numeric = self.query.trim().parse::<u32>().ok();
+
+        let mut scored: Vec<(i32, u32, &'static str)> = all_keys()
+            .iter()
+            .filter_map(|(code, name)| {
+                if Some(*code) == numeric {
+                    return Some((i32::MAX, *code, name.as_str()));
+                }
+                fuzzy_score(&self.query, name).map(|s| (s, *code, name.as_str()))
+            })
+            .collect();
+        if !self.query.is_empty() {
+            scored.sort_by(|a, b| b.0.cmp(&a.0).then(a.1.cmp(&b.1)));
+        }
+
+        self.matches = scored.into_iter().map(|(_, code, name)| (code, name)).collect();
+        self.state.select(if self.matches.is_empty() { None } else { Some(0) });
+    }
+
+    pub fn push(&mut self, c: char) {
+        self.query.push(c);
+        self.refilter();
+    }
+
+    pub fn pop(&mut self) {
+        self.query.pop();
+        self.refilter();
+    }
+
+    pub fn next(&mut self) {
+        let i = self.state.selected().unwrap_or(0);
+        if i + 1 < self.matches.len() {
+            self.state.select(Some(i + 1));
+        }
+    }
+
+    pub fn prev(&mut self) {
+        let i = self.state.selected().unwrap_or(0);
+        if i > 0 {
+            self.state.select(Some(i - 1));
+        }
+    }
+
+    pub fn selected(&self) -> Option<u32> {
+        self.state
+            .selected()
+            .and_then(|i| self.matches.get(i))
+            .map(|(code, _)| *code)
+    }
+}
+
+/// A key read in capture mode, with the modifier keys held alongside it.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CapturedKey {
+    pub code: u32,
+    /// Modifier keycodes, in the order they should be pressed.
+    pub modifiers: Vec<u32>,
+}
+
+impl CapturedKey {
+    /// `LEFTCTRL+LEFTSHIFT+A`
+    pub fn label(&self) -> String {
+        self.modifiers
+            .iter()
+            .chain(std::iter::once(&self.code))
+            .map(|&code| short_key_label(code))
+            .collect::<Vec<_>>()
+            .join("+")
+    }
+}
+
+/// Modifier keycodes, in the order they are pressed for a combination.
+pub const MODIFIER_KEYS: [KeyCode; 8] = [
+    KeyCode::KEY_LEFTCTRL,
+    KeyCode::KEY_RIGHTCTRL,
+    KeyCode::KEY_LEFTSHIFT,
+    KeyCode::KEY_RIGHTSHIFT,
+    KeyCode::KEY_LEFTALT,
+    KeyCode::KEY_RIGHTALT,
+    KeyCode::KEY_LEFTMETA,
+    KeyCode::KEY_RIGHTMETA,
+];
+
+pub fn is_modifier(code: u32) -> bool {
+    MODIFIER_KEYS.iter().any(|k| k.code() as u32 == code)
+}
+
+/// Letters in alphabetical order; their keycodes follow the QWERTY rows.
+const LETTERS: [KeyCode; 26] = [
+    KeyCode::KEY_A,
+    KeyCode::KEY_B,
+    KeyCode::KEY_C,
+    KeyCode::KEY_D,
+    KeyCode::KEY_E,
+    KeyCode::KEY_F,
+    KeyCode::KEY_G,
+    KeyCode::KEY_H,
+    KeyCode::KEY_I,
+    KeyCode::KEY_J,
+    KeyCode::KEY_K,
+    KeyCode::KEY_L,
+    KeyCode::KEY_M,
+    KeyCode::KEY_N,
+    KeyCode::KEY_O,
+    KeyCode::KEY_P,
+    KeyCode::KEY_Q,
+    KeyCode::KEY_R,
+    KeyCode::KEY_S,
+    KeyCode::KEY_T,
+    KeyCode::KEY_U,
+    KeyCode::KEY_V,
+    KeyCode::KEY_W,
+    KeyCode::KEY_X,
+    KeyCode::KEY_Y,
+    KeyCode::KEY_Z,
+];
+
+/// Key that types `c` on a US layout, and whether Shift is needed for it.
+fn char_to_key(c: char) -> Option<(KeyCode, bool)> {
+    if c.is_ascii_alphabetic() {
+        let letter = LETTERS[(c.to_ascii_lowercase() as u8 - b'a') as usize];
+        return Some((letter, c.is_ascii_uppercase()));
+    }
+    let key = match c {
+        '1' | '!' => KeyCode::KEY_1,
+        '2' | '@' => KeyCode::KEY_2,
+        '3' | '#' => KeyCode::KEY_3,
+        '4' | '$' => KeyCode::KEY_4,
+        '5' | '%' => KeyCode::KEY_5,
+        '6' | '^' => KeyCode::KEY_6,
+        '7' | '&' => KeyCode::KEY_7,
+        '8' | '*' => KeyCode::KEY_8,
+        '9' | '(' => KeyCode::KEY_9,
+        '0' | ')' => KeyCode::KEY_0,
+        '-' | '_' => KeyCode::KEY_MINUS,
+        '=' | '+' => KeyCode::KEY_EQUAL,
+        '[' | '{' => KeyCode::KEY_LEFTBRACE,
+        ']' | '}' => KeyCode::KEY_RIGHTBRACE,
+        '\\' | '|' => KeyCode::KEY_BACKSLASH,
+        ';' | ':' => KeyCode::KEY_SEMICOLON,
+        '\'' | '"' => KeyCode::KEY_APOSTROPHE,
+        '`' | '~' => KeyCode::KEY_GRAVE,
+        ',' | '<' => KeyCode::KEY_COMMA,
+        '.' | '>' => KeyCode::KEY_DOT,
+        '/' | '?' => KeyCode::KEY_SLASH,
+        ' ' => KeyCode::KEY_SPACE,
+        _ => return None,
+    };
+    Some((key, "!@#$%^&*()_+{}|:\"~<>?".contains(c)))
+}
+
+fn function_key(n: u8) -> Option<KeyCode> {
+    let code = match n {
+        1..=10 => KeyCode::KEY_F1.code() + (n as u16 - 1),
+        11 => KeyCode::KEY_F11.code(),
+        12 => KeyCode::KEY_F12.code(),
+        13..=24 => KeyCode::KEY_F13.code() + (n as u16 - 13),
+        _ => return None,
+    };
+    Some(KeyCode::new(code))
+}
+
+fn media_key(key: MediaKeyCode) -> Option<KeyCode> {
+    Some(match key {
+        MediaKeyCode::Play => KeyCode::KEY_PLAY,
+        MediaKeyCode::Pause => KeyCode::KEY_PAUSECD,
+        MediaKeyCode::PlayPause => KeyCode::KEY_PLAYPAUSE,
+        MediaKeyCode::Stop => KeyCode::KEY_STOPCD,
+        MediaKeyCode::FastForward => KeyCode::KEY_FASTFORWARD,
+        MediaKeyCode::Rewind => KeyCode::KEY_REWIND,
+        MediaKeyCode::TrackNext => KeyCode::KEY_NEXTSONG,
+        MediaKeyCode::TrackPrevious => KeyCode::KEY_PREVIOUSSONG,
+        MediaKeyCode::Record => KeyCode::KEY_RECORD,
+        MediaKeyCode::LowerVolume => KeyCode::KEY_VOLUMEDOWN,
+        MediaKeyCode::RaiseVolume => KeyCode::KEY_VOLUMEUP,
+        MediaKeyCode::MuteVolume => KeyCode::KEY_MUTE,
+        MediaKeyCode::Reverse => return None,
+    })
+}
+
+fn modifier_key(key: ModifierKeyCode) -> Option<KeyCode> {
+    Some(match key {
+        ModifierKeyCode::LeftShift => KeyCode::KEY_LEFTSHIFT,
+        ModifierKeyCode::LeftControl => KeyCode::KEY_LEFTCTRL,
+        ModifierKeyCode::LeftAlt => KeyCode::KEY_LEFTALT,
+        ModifierKeyCode::LeftSuper | ModifierKeyCode::LeftMeta => KeyCode::KEY_LEFTMETA,
+        ModifierKeyCode::RightShift => KeyCode::KEY_RIGHTSHIFT,
+        ModifierKeyCode::RightControl => KeyCode::KEY_RIGHTCTRL,
+        ModifierKeyCode::RightAlt => KeyCode::KEY_RIGHTALT,
+        ModifierKeyCode::RightSuper | ModifierKeyCode::RightMeta => KeyCode::KEY_RIGHTMETA,
+        _ => return None,
+    })
+}
+
+/// Translate a terminal key event into the Linux keycode that produces it.
+///
+/// The terminal reports characters rather than scancodes, so characters are
+/// mapped through a US layout table: `A` becomes `KEY_A` with Shift held, and
+/// `!` becomes `KEY_1` with Shift. Ctrl, Alt and Super are reported as the
+/// left-hand modifier keys. Returns `None` for keys with no Linux equivalent.
+pub fn from_crossterm(event: &KeyEvent) -> Option<CapturedKey> {
+    let mut shift = event.modifiers.contains(KeyModifiers::SHIFT);
+    let key = match event.code {
+        TermKey::Char(c) => {
+            let (key, needs_shift) = char_to_key(c)?;
+            shift |= needs_shift;
+            key
+        }
+        TermKey::Backspace => KeyCode::KEY_BACKSPACE,
+        TermKey::Enter => KeyCode::KEY_ENTER,
+        TermKey::Left => KeyCode::KEY_LEFT,
+        TermKey::Right => KeyCode::KEY_RIGHT,
+        TermKey::Up => KeyCode::KEY_UP,
+        TermKey::Down => KeyCode::KEY_DOWN,
+        TermKey::Home => KeyCode::KEY_HOME,
+        TermKey::End => KeyCode::KEY_END,
+        TermKey::PageUp => KeyCode::KEY_PAGEUP,
+        TermKey::PageDown => KeyCode::KEY_PAGEDOWN,
+        TermKey::Tab => KeyCode::KEY_TAB,
+        TermKey::BackTab => {
+            shift = true;
+            KeyCode::KEY_TAB
+        }
+        TermKey::Delete => KeyCode::KEY_DELETE,
+        TermKey::Insert => KeyCode::KEY_INSERT,
+        TermKey::F(n) => function_key(n)?,
+        TermKey::Esc => KeyCode::KEY_ESC,
+        TermKey::CapsLock => KeyCode::KEY_CAPSLOCK,
+        TermKey::ScrollLock => KeyCode::KEY_SCROLLLOCK,
+        TermKey::NumLock => KeyCode::KEY_NUMLOCK,
+        TermKey::PrintScreen => KeyCode::KEY_SYSRQ,
+        TermKey::Pause => KeyCode::KEY_PAUSE,
+        TermKey::Menu => KeyCode::KEY_COMPOSE,
+        TermKey::Media(media) => media_key(media)?,
+        TermKey::Modifier(modifier) => {
+            // A lone modifier press is the key itself, not a combination
+            let key = modifier_key(modifier)?;
+            return Some(CapturedKey {
+                code: key.code() as u32,
+                modifiers: Vec::new(),
+            });
+        }
+        _ => return None,
+    };
+
+    let mut modifiers = Vec::new();
+    if event.modifiers.contains(KeyModifiers::CONTROL) {
+        modifiers.push(KeyCode::KEY_LEFTCTRL.code() as u32);
+    }
+    if shift {
+        modifiers.push(KeyCode::KEY_LEFTSHIFT.code() as u32);
+    }
+    if event.modifiers.contains(KeyModifiers::ALT) {
+        modifiers.push(KeyCode::KEY_LEFTALT.code() as u32);
+    }
+    if event.modifiers.intersects(KeyModifiers::SUPER | KeyModifiers::META) {
+        modifiers.push(KeyCode::KEY_LEFTMETA.code() as u32);
+    }
+
+    Some(CapturedKey {
+        code: key.code() as u32,
+        modifiers,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn capture(code: TermKey, modifiers: KeyModifiers) -> Option<CapturedKey> {
+        from_crossterm(&KeyEvent::new(code, modifiers))
+    }
+
+    fn key(code: KeyCode, modifiers: &[KeyCode]) -> Option<CapturedKey> {
+        Some(CapturedKey {
+            code: code.code() as u32,
+            modifiers: modifiers.iter().map(|m| m.code() as u32).collect(),
+        })
+    }
+
+    #[test]
+    fn shifted_symbols_map_to_their_us_key() {
+        let cases = [
+            ('!', KeyCode::KEY_1),
+            ('@', KeyCode::KEY_2),
+            ('_', KeyCode::KEY_MINUS),
+            ('+', KeyCode::KEY_EQUAL),
+            ('{', KeyCode::KEY_LEFTBRACE),
+            ('|', KeyCode::KEY_BACKSLASH),
+            (':', KeyCode::KEY_SEMICOLON),
+            ('"', KeyCode::KEY_APOSTROPHE),
+            ('~', KeyCode::KEY_GRAVE),
+            ('<', KeyCode::KEY_COMMA),
+            ('?', KeyCode::KEY_SLASH),
+        ];
+        for (c, code) in cases {
+            let expected = key(code, &[KeyCode::KEY_LEFTSHIFT]);
+            assert_eq!(capture(TermKey::Char(c), KeyModifiers::NONE), expected, "{:?}", c);
+            // Some terminals report the shift as well; it is only added once
+            assert_eq!(capture(TermKey::Char(c), KeyModifiers::SHIFT), expected, "{:?}", c);
+        }
+    }
+
+    #[test]
+    fn unshifted_characters_have_no_shift() {
+        let cases = [
+            ('a', KeyCode::KEY_A),
+            ('1', KeyCode::KEY_1),
+            ('-', KeyCode::KEY_MINUS),
+            ('\'', KeyCode::KEY_APOSTROPHE),
+            ('/', KeyCode::KEY_SLASH),
+            (' ', KeyCode::KEY_SPACE),
+        ];
+        for (c, code) in cases {
+            assert_eq!(capture(TermKey::Char(c), KeyModifiers::NONE), key(code, &[]), "{:?}", c);
+        }
+        assert_eq!(
+            capture(TermKey::Char('A'), KeyModifiers::NONE),
+            key(KeyCode::KEY_A, &[KeyCode::KEY_LEFTSHIFT])
+        );
+    }
+
+    #[test]
+    fn modifiers_come_in_a_fixed_order() {
+        assert_eq!(
+            capture(TermKey::Char('c'), KeyModifiers::ALT | KeyModifiers::CONTROL),
+            key(KeyCode::KEY_C, &[KeyCode::KEY_LEFTCTRL, KeyCode::KEY_LEFTALT])
+        );
+        assert_eq!(
+            capture(TermKey::BackTab, KeyModifiers::NONE),
+            key(KeyCode::KEY_TAB, &[KeyCode::KEY_LEFTSHIFT])
+        );
+    }
+
+    #[test]
+    fn special_keys() {
+        assert_eq!(capture(TermKey::F(1), KeyModifiers::NONE), key(KeyCode::KEY_F1, &[]));
+        assert_eq!(capture(TermKey::F(13), KeyModifiers::NONE), key(KeyCode::KEY_F13, &[]));
+        assert_eq!(capture(TermKey::F(25), KeyModifiers::NONE), None);
+        assert_eq!(capture(TermKey::Esc, KeyModifiers::NONE), key(KeyCode::KEY_ESC, &[]));
+        assert_eq!(capture(TermKey::Char('é'), KeyModifiers::NONE), None);
+        // A lone modifier is the key itself, without itself as a modifier
+        assert_eq!(
+            capture(TermKey::Modifier(ModifierKeyCode::LeftShift), KeyModifiers::SHIFT),
+            key(KeyCode::KEY_LEFTSHIFT, &[])
+        );
+    }
+}
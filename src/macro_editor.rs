@@ -0,0 +1,348 @@
+use crate::dbus::device::MacroEvent;
+use ratatui::widgets::ListState;
+use std::time::SystemTime;
+
+/// Editing state for the button macro popup.
+pub struct MacroEditor {
+    pub steps: Vec<MacroEvent>,
+    pub state: ListState,
+    /// Delay being typed in on the prompt line, in milliseconds.
+    pub delay_input: Option<String>,
+}
+
+impl MacroEditor {
+    pub const DEFAULT_DELAY: u32 = 50;
+
+    pub fn new(steps: Vec<MacroEvent>) -> Self {
+        let mut state = ListState::default();
+        if !steps.is_empty() {
+            state.select(Some(0));
+        }
+        MacroEditor {
+            steps,
+            state,
+            delay_input: None,
+        }
+    }
+
+    pub fn next(&mut self) {
+        let i = self.state.selected().unwrap_or(0);
+        if i + 1 < self.steps.len() {
+            self.state.select(Some(i + 1));
+        }
+    }
+
+    pub fn prev(&mut self) {
+        let i = self.state.selected().unwrap_or(0);
+        if i > 0 {
+            self.state.select(Some(i - 1));
+        }
+    }
+
+    /// Position new steps are inserted at: right after the selected one.
+    fn insert_at(&self) -> usize {
+        self.state.selected().map(|i| i + 1).unwrap_or(self.steps.len())
+    }
+
+    /// Insert a key combination: the modifiers are pressed in order, the key
+    /// is tapped, and the modifiers are released in reverse order.
+    pub fn add_key_combo(&mut self, modifiers: &[u32], key: u32) {
+        let at = self.insert_at();
+        let steps: Vec<MacroEvent> = modifiers
+            .iter()
+            .map(|&m| MacroEvent::KeyPress(m))
+            .chain([MacroEvent::KeyPress(key), MacroEvent::KeyRelease(key)])
+            .chain(modifiers.iter().rev().map(|&m| MacroEvent::KeyRelease(m)))
+            .collect();
+        let last = at + steps.len() - 1;
+        self.steps.splice(at..at, steps);
+        self.state.select(Some(last));
+    }
+
+    pub fn add_delay(&mut self, ms: u32) {
+        let at = self.insert_at();
+        self.steps.insert(at, MacroEvent::Wait(ms));
+        self.state.select(Some(at));
+    }
+
+    pub fn remove(&mut self) {
+        let Some(i) = self.state.selected() else {
+            return;
+        };
+        if i >= self.steps.len() {
+            return;
+        }
+        self.steps.remove(i);
+        if self.steps.is_empty() {
+            self.state.select(None);
+        } else if i >= self.steps.len() {
+            self.state.select(Some(self.steps.len() - 1));
+        }
+    }
+
+    pub fn move_up(&mut self) {
+        if let Some(i) = self.state.selected()
+            && i > 0
+            && i < self.steps.len()
+        {
+            self.steps.swap(i, i - 1);
+            self.state.select(Some(i - 1));
+        }
+    }
+
+    pub fn move_down(&mut self) {
+        if let Some(i) = self.state.selected()
+            && i + 1 < self.steps.len()
+        {
+            self.steps.swap(i, i + 1);
+            self.state.select(Some(i + 1));
+        }
+    }
+
+    /// Turn a key press into a release or vice versa.
+    pub fn toggle_direction(&mut self) {
+        if let Some(step) = self.state.selected().and_then(|i| self.steps.get_mut(i)) {
+            *step = match *step {
+                MacroEvent::KeyPress(k) => MacroEvent::KeyRelease(k),
+                MacroEvent::KeyRelease(k) => MacroEvent::KeyPress(k),
+                other => other,
+            };
+        }
+    }
+
+    pub fn start_delay_prompt(&mut self) {
+        self.delay_input = Some(Self::DEFAULT_DELAY.to_string());
+    }
+
+    pub fn push_prompt(&mut self, c: char) {
+        if let Some(ref mut input) = self.delay_input
+            && c.is_ascii_digit()
+            && input.len() < 6
+        {
+            input.push(c);
+        }
+    }
+
+    pub fn pop_prompt(&mut self) {
+        if let Some(ref mut input) = self.delay_input {
+            input.pop();
+        }
+    }
+
+    /// Insert the typed delay, if it parses, and close the prompt.
+    pub fn submit_prompt(&mut self) {
+        if let Some(ms) = self.delay_input.take().and_then(|input| input.parse::<u32>().ok()) {
+            self.add_delay(ms);
+        }
+    }
+}
+
+/// Key events recorded live from a keyboard, turned into macro steps with the
+/// time between events kept as delays.
+pub struct MacroRecorder {
+    pub steps: Vec<MacroEvent>,
+    pub state: ListState,
+    last_event: Option<SystemTime>,
+}
+
+impl MacroRecorder {
+    pub fn new() -> Self {
+        MacroRecorder {
+            steps: Vec::new(),
+            state: ListState::default(),
+            last_event: None,
+        }
+    }
+
+    /// Append a key event that happened at `time`, preceded by the delay since
+    /// the previous one.
+    pub fn push(&mut self, event: MacroEvent, time: SystemTime) {
+        if let Some(last) = self.last_event {
+            let ms = time.duration_since(last).unwrap_or_default().as_millis() as u32;
+            if ms > 0 {
+                self.steps.push(MacroEvent::Wait(ms));
+            }
+        }
+        self.last_event = Some(time);
+        self.steps.push(event);
+        self.state.select(Some(self.steps.len() - 1));
+    }
+
+    /// Tidy the recording once it stops. Releases of keys that were already
+    /// down when recording started and presses still held when it stopped
+    /// (such as the key that stopped it) are dropped, along with the delays
+    /// at either end.
+    pub fn finish(&mut self) {
+        self.last_event = None;
+        self.tidy();
+    }
+
+    fn tidy(&mut self) {
+        let mut down = Vec::new();
+        self.steps.retain(|step| match *step {
+            MacroEvent::KeyPress(k) => {
+                down.push(k);
+                true
+            }
+            MacroEvent::KeyRelease(k) => match down.iter().position(|&d| d == k) {
+                Some(i) => {
+                    down.remove(i);
+                    true
+                }
+                None => false,
+            },
+            MacroEvent::Wait(_) => true,
+        });
+
+        let mut released = Vec::new();
+        let mut keep = vec![true; self.steps.len()];
+        for (i, step) in self.steps.iter().enumerate().rev() {
+            match *step {
+                MacroEvent::KeyRelease(k) => released.push(k),
+                MacroEvent::KeyPress(k) => match released.iter().position(|&r| r == k) {
+                    Some(j) => {
+                        released.remove(j);
+                    }
+                    None => keep[i] = false,
+                },
+                MacroEvent::Wait(_) => {}
+            }
+        }
+        let mut keep = keep.into_iter();
+        self.steps.retain(|_| keep.next().unwrap_or(true));
+
+        // Delays only make sense between key events
+        let mut steps: Vec<MacroEvent> = Vec::with_capacity(self.steps.len());
+        for step in self.steps.drain(..) {
+            match (steps.last_mut(), step) {
+                (None, MacroEvent::Wait(_)) => {}
+                (Some(MacroEvent::Wait(prev)), MacroEvent::Wait(ms)) => *prev = prev.saturating_add(ms),
+                _ => steps.push(step),
+            }
+        }
+        if matches!(steps.last(), Some(MacroEvent::Wait(_))) {
+            steps.pop();
+        }
+        self.steps = steps;
+
+        let selected = self.state.selected().unwrap_or(0);
+        self.state.select(if self.steps.is_empty() {
+            None
+        } else {
+            Some(selected.min(self.steps.len() - 1))
+        });
+    }
+
+    pub fn next(&mut self) {
+        let i = self.state.selected().unwrap_or(0);
+        if i + 1 < self.steps.len() {
+            self.state.select(Some(i + 1));
+        }
+    }
+
+    pub fn prev(&mut self) {
+        let i = self.state.selected().unwrap_or(0);
+        if i > 0 {
+            self.state.select(Some(i - 1));
+        }
+    }
+
+    /// Drop every step before the selected one.
+    pub fn trim_start(&mut self) {
+        if let Some(i) = self.state.selected() {
+            self.steps.drain(..i);
+            self.state.select(Some(0));
+            self.tidy();
+        }
+    }
+
+    /// Drop every step after the selected one.
+    pub fn trim_end(&mut self) {
+        if let Some(i) = self.state.selected() {
+            self.steps.truncate(i + 1);
+            self.tidy();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use MacroEvent::{KeyPress, KeyRelease, Wait};
+    use std::time::Duration;
+
+    fn finished(steps: Vec<MacroEvent>) -> Vec<MacroEvent> {
+        let mut recorder = MacroRecorder::new();
+        recorder.steps = steps;
+        recorder.finish();
+        recorder.steps
+    }
+
+    #[test]
+    fn push_records_delays_between_events() {
+        let start = SystemTime::UNIX_EPOCH;
+        let mut recorder = MacroRecorder::new();
+        recorder.push(KeyPress(30), start);
+        recorder.push(KeyRelease(30), start + Duration::from_millis(40));
+        recorder.push(KeyPress(31), start + Duration::from_millis(40));
+        assert_eq!(recorder.steps, vec![KeyPress(30), Wait(40), KeyRelease(30), KeyPress(31)]);
+        assert_eq!(recorder.state.selected(), Some(3));
+    }
+
+    #[test]
+    fn finish_drops_unmatched_presses_and_releases() {
+        let cases = [
+            // The key that started recording is released inside it
+            (vec![KeyRelease(28), KeyPress(30), KeyRelease(30)], vec![KeyPress(30), KeyRelease(30)]),
+            // The key that stopped it is still held
+            (vec![KeyPress(30), KeyRelease(30), KeyPress(1)], vec![KeyPress(30), KeyRelease(30)]),
+            // Nested keys keep their pairing
+            (
+                vec![KeyPress(42), KeyPress(30), KeyRelease(30), KeyRelease(42)],
+                vec![KeyPress(42), KeyPress(30), KeyRelease(30), KeyRelease(42)],
+            ),
+            // A repeated press only keeps the one that has a release
+            (vec![KeyPress(30), KeyPress(30), KeyRelease(30)], vec![KeyPress(30), KeyRelease(30)]),
+            (vec![KeyRelease(30), KeyPress(1)], vec![]),
+        ];
+        for (steps, expected) in cases {
+            assert_eq!(finished(steps.clone()), expected, "{:?}", steps);
+        }
+    }
+
+    #[test]
+    fn finish_drops_leading_and_trailing_waits() {
+        let cases = [
+            (
+                vec![Wait(100), KeyPress(30), Wait(20), KeyRelease(30), Wait(300)],
+                vec![KeyPress(30), Wait(20), KeyRelease(30)],
+            ),
+            // Waits around a dropped event end up at the edges or merged
+            (
+                vec![KeyRelease(28), Wait(50), KeyPress(30), KeyRelease(30), Wait(60), KeyPress(1)],
+                vec![KeyPress(30), KeyRelease(30)],
+            ),
+            (
+                vec![KeyPress(30), Wait(10), KeyRelease(28), Wait(15), KeyRelease(30)],
+                vec![KeyPress(30), Wait(25), KeyRelease(30)],
+            ),
+            (vec![Wait(5), Wait(10)], vec![]),
+        ];
+        for (steps, expected) in cases {
+            assert_eq!(finished(steps.clone()), expected, "{:?}", steps);
+        }
+    }
+
+    #[test]
+    fn finish_keeps_selection_in_range() {
+        let mut recorder = MacroRecorder::new();
+        recorder.steps = vec![KeyPress(30), KeyRelease(30), Wait(100), KeyPress(1)];
+        recorder.state.select(Some(3));
+        recorder.finish();
+        assert_eq!(recorder.state.selected(), Some(1));
+
+        recorder.steps = vec![Wait(100)];
+        recorder.finish();
+        assert_eq!(recorder.state.selected(), None);
+    }
+}
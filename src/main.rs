@@ -1,11 +1,31 @@
+mod cli;
+mod color;
+mod config;
 mod dbus;
+mod history;
 mod input;
+mod keys;
+mod macro_editor;
+mod state;
 
-use dbus::device::{ButtonAction, MouseDevice};
+use color::{ColorPicker, PickerField};
+use dbus::device::{
+    ActionCategory, ButtonAction, Dpi, SPECIAL_ACTIONS, LedMode, MacroEvent, MouseDevice, MouseLed, MouseProfile,
+    MouseResolution, Change, Edit,
+};
+use zbus::zvariant::OwnedObjectPath;
+use config::DeviceConfig;
+use dbus::error::RatbagError;
+use dbus::watch::DeviceEvent;
+use dbus::worker::{Job, Outcome, Worker};
+use history::History;
+use keys::{CapturedKey, KeyPicker};
+use macro_editor::{MacroEditor, MacroRecorder};
+use futures_lite::{future, StreamExt};
 use zbus::Connection;
 
 use crossterm::{
-    event::{self, Event, KeyCode},
+    event::{Event, EventStream, KeyCode, KeyEvent, KeyModifiers},
     execute,
     terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
 };
@@ -13,71 +33,370 @@ use ratatui::{
     backend::CrosstermBackend,
     layout::{Alignment, Constraint, Direction, Layout, Rect},
     style::{Color, Modifier, Style},
-    text::{Line, Span},
-    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph},
+    text::{Line, Span, Text},
+    widgets::{Block, Borders, Clear, List, ListItem, ListState, Paragraph, Tabs},
     Frame, Terminal,
 };
+use std::collections::VecDeque;
 use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, Instant, SystemTime};
 use tokio::sync::mpsc;
 
+const LED_BRIGHTNESS_STEP: u32 = 16;
+const LED_DURATION_STEP: u32 = 250;
+const COLOR_SLIDER_WIDTH: u32 = 24;
+
 #[derive(PartialEq)]
 enum Panel {
+    Profiles,
     Dpi,
+    ReportRate,
+    Sensor,
     Buttons,
+    Leds,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum Axis {
+    X,
+    Y,
+}
+
+/// How long a primary button remap stays on the mouse unless confirmed.
+const REVERT_TIMEOUT: Duration = Duration::from_secs(15);
+
+/// How long ratbagd has to be quiet after reporting a change before the
+/// devices are read again.
+const SYNC_DELAY: Duration = Duration::from_millis(250);
+
+/// How long to give ratbagd to find its devices after it starts.
+const DAEMON_START_DELAY: Duration = Duration::from_secs(1);
+
+/// How often to look for ratbagd and devices while waiting on startup.
+const WAIT_POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+/// Most keys kept while a write is in flight; any more are dropped.
+const MAX_QUEUED_KEYS: usize = 32;
+
+/// How long after a key is captured from evdev the terminal's copy of it may
+/// still arrive.
+const CAPTURE_ECHO_WINDOW: Duration = Duration::from_millis(250);
+
+/// How often the spinner and remap countdown are redrawn.
+const ANIMATION_TICK: Duration = Duration::from_millis(100);
+
+const SPINNER: &[&str] = &["⠋", "⠙", "⠹", "⠸", "⠼", "⠴", "⠦", "⠧", "⠇", "⠏"];
+
+/// A ratbagd operation handed to the worker.
+enum Task {
+    Load,
+    /// Writing staged changes; `risky` are the ones that remap a primary
+    /// button and need confirming.
+    Write { name: String, risky: Vec<Edit> },
+    Revert { name: String },
+}
+
+impl Task {
+    fn label(&self) -> String {
+        match self {
+            Task::Load => "Reading devices".into(),
+            Task::Write { name, .. } => format!("Writing to {}", name),
+            Task::Revert { name } => format!("Reverting button changes on {}", name),
+        }
+    }
+}
+
+struct InFlight {
+    id: u64,
+    task: Task,
+    started: Instant,
+}
+
+/// Operations the worker hasn't answered yet, and keys pressed while waiting
+/// for a write to finish.
+#[derive(Default)]
+struct Jobs {
+    in_flight: Vec<InFlight>,
+    queued_keys: VecDeque<KeyEvent>,
+    /// Quit once the writes in flight are done and their primary button
+    /// remaps confirmed, unless one of them fails or is reverted.
+    quit_pending: bool,
+}
+
+impl Jobs {
+    fn start(&mut self, id: u64, task: Task) {
+        self.in_flight.push(InFlight {
+            id,
+            task,
+            started: Instant::now(),
+        });
+    }
+
+    fn finish(&mut self, id: u64) -> Option<Task> {
+        let i = self.in_flight.iter().position(|job| job.id == id)?;
+        Some(self.in_flight.remove(i).task)
+    }
+
+    /// The next key to handle, unless a write has to finish first.
+    fn next_key(&mut self) -> Option<KeyEvent> {
+        if self.writing() {
+            return None;
+        }
+        self.queued_keys.pop_front()
+    }
+
+    /// Queue a key from the terminal; returns whether the screen needs
+    /// redrawing. Keys pressed during a write are kept up to a limit.
+    fn push_event(&mut self, event: Event) -> bool {
+        match event {
+            Event::Key(key) => {
+                // The footer says when the queue is full
+                if !self.writing() || self.queued_keys.len() < MAX_QUEUED_KEYS {
+                    self.queued_keys.push_back(key);
+                }
+                true
+            }
+            Event::Resize(..) => true,
+            _ => false,
+        }
+    }
+
+    /// Whether something is being written. Keys are queued meanwhile, so
+    /// nothing is staged on top of a write that may still fail.
+    fn writing(&self) -> bool {
+        self.in_flight.iter().any(|job| !matches!(job.task, Task::Load))
+    }
+}
+
+/// Primary button remaps that have been written to a mouse and are put back
+/// unless confirmed from the keyboard before `deadline`.
+struct PendingRevert {
+    /// The device, by path so the revert survives devices coming and going.
+    device_path: OwnedObjectPath,
+    name: String,
+    edits: Vec<Edit>,
+    deadline: Instant,
+}
+
+/// Where a key chosen in the key picker ends up.
+#[derive(Clone, Copy, PartialEq)]
+enum KeyTarget {
+    Button,
+    Macro,
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum SensorRow {
+    AngleSnapping,
+    Debounce,
 }
 
 #[derive(PartialEq)]
 enum Mode {
     Normal,
+    EditingDpi,
     EditingButton,
+    PickingKey,
+    CapturingKey,
+    RecordingMacro,
+    EditingMacro,
+    EditingLed,
+    EditingDebounce,
+    PickingColor,
+    SelectingDevice,
+    ReviewingChanges,
+    ImportingConfig,
+    ConfirmingRemap,
+    ConfirmingQuit,
     Testing,
 }
 
 struct App {
-    device: MouseDevice,
+    devices: Vec<MouseDevice>,
+    device: usize,
+    profile: usize,
     panel: Panel,
     mode: Mode,
+    device_state: ListState,
+    profile_state: ListState,
     dpi_state: ListState,
+    rate_state: ListState,
+    sensor_state: ListState,
     button_state: ListState,
+    led_state: ListState,
     popup_state: ListState,
     popup_options: Vec<ButtonAction>,
+    /// Categories the selected button supports, shown as tabs in the editor.
+    action_tabs: Vec<ActionCategory>,
+    action_tab: usize,
+    key_picker: Option<KeyPicker>,
+    key_target: KeyTarget,
+    /// Receives the key read from the keyboard's evdev node in capture mode;
+    /// `None` when no keyboard node is readable and the terminal is used.
+    key_capture: Option<mpsc::Receiver<CapturedKey>>,
+    capture_keyboards: usize,
+    /// Whether the terminal has delivered its own copy of a key during capture.
+    capture_echoed: bool,
+    /// The terminal's copy of a key captured from evdev is still to come, and
+    /// is dropped if it arrives before this.
+    capture_echo_until: Option<Instant>,
+    macro_recorder: Option<MacroRecorder>,
+    /// Staged changes listed in the pending-changes view.
+    pending: Vec<Edit>,
+    history: History,
+    /// One per device with an unconfirmed primary button remap, oldest first.
+    reverts: Vec<PendingRevert>,
+    /// Live key events while a macro is being recorded; `None` once stopped.
+    recording: Option<mpsc::Receiver<(MacroEvent, SystemTime)>>,
+    dpi_options: Vec<u32>,
+    /// Value being built up in the DPI popup for per-axis resolutions.
+    dpi_edit: Dpi,
+    dpi_axis: Axis,
+    dpi_lock: bool,
+    led_options: Vec<LedMode>,
+    debounce_options: Vec<u32>,
+    color_picker: Option<ColorPicker>,
+    macro_editor: Option<MacroEditor>,
     status: Option<String>,
     last_input: Option<String>,
+    import_path: String,
+    /// The device shown has been unplugged and there is no other to show.
+    disconnected: bool,
+    /// ratbagd has left the bus; the devices shown are as last read.
+    daemon_down: bool,
+    jobs: Jobs,
 }
 
 impl App {
-    fn new(device: MouseDevice) -> Self {
-        let dpi_index = device
-            .valid_dpis
-            .iter()
-            .position(|&d| d == device.dpi)
-            .unwrap_or(0);
+    fn new(devices: Vec<MouseDevice>, device: usize) -> Self {
+        let profile = devices[device].active_profile().unwrap_or(0);
 
-        let mut dpi_state = ListState::default();
-        dpi_state.select(Some(dpi_index));
+        let mut device_state = ListState::default();
+        device_state.select(Some(device));
 
-        let mut button_state = ListState::default();
-        button_state.select(Some(0));
+        let mut profile_state = ListState::default();
+        profile_state.select(Some(profile));
 
         let mut popup_state = ListState::default();
         popup_state.select(Some(0));
 
-        App {
+        let mut app = App {
+            devices,
             device,
+            profile,
             panel: Panel::Dpi,
             mode: Mode::Normal,
-            dpi_state,
-            button_state,
+            device_state,
+            profile_state,
+            dpi_state: ListState::default(),
+            rate_state: ListState::default(),
+            sensor_state: ListState::default(),
+            button_state: ListState::default(),
+            led_state: ListState::default(),
             popup_state,
             popup_options: Vec::new(),
+            action_tabs: Vec::new(),
+            action_tab: 0,
+            key_picker: None,
+            key_target: KeyTarget::Button,
+            key_capture: None,
+            capture_keyboards: 0,
+            capture_echoed: false,
+            capture_echo_until: None,
+            macro_recorder: None,
+            pending: Vec::new(),
+            history: History::default(),
+            reverts: Vec::new(),
+            recording: None,
+            dpi_options: Vec::new(),
+            dpi_edit: Dpi::Unified(0),
+            dpi_axis: Axis::X,
+            dpi_lock: true,
+            led_options: Vec::new(),
+            debounce_options: Vec::new(),
+            color_picker: None,
+            macro_editor: None,
             status: None,
             last_input: None,
+            import_path: String::new(),
+            disconnected: false,
+            daemon_down: false,
+            jobs: Jobs::default(),
+        };
+        app.select_profile(profile);
+        app
+    }
+
+    fn device(&self) -> &MouseDevice {
+        &self.devices[self.device]
+    }
+
+    fn device_mut(&mut self) -> &mut MouseDevice {
+        &mut self.devices[self.device]
+    }
+
+    fn profile(&self) -> &MouseProfile {
+        &self.device().profiles[self.profile]
+    }
+
+    /// Show `index` in the DPI, Report Rate, Sensor, Buttons and LEDs panels,
+    /// resetting their selection.
+    fn select_profile(&mut self, index: usize) {
+        self.profile = index;
+        self.profile_state.select(Some(index));
+
+        let profile = &self.devices[self.device].profiles[index];
+        let dpi_index = profile.active_resolution();
+        let rate_index = profile
+            .report_rates
+            .iter()
+            .position(|&r| r == profile.report_rate)
+            .unwrap_or(0);
+        self.dpi_state.select(Some(dpi_index));
+        self.rate_state.select(Some(rate_index));
+        self.sensor_state.select(Some(0));
+        self.button_state.select(Some(0));
+        self.led_state.select(Some(0));
+
+        if self.panel == Panel::Leds && self.profile().leds.is_empty() {
+            self.panel = Panel::Buttons;
+        }
+        if self.panel == Panel::ReportRate && self.profile().report_rates.is_empty() {
+            self.panel = Panel::Dpi;
+        }
+        if self.panel == Panel::Sensor && !self.profile().has_sensor_settings() {
+            self.panel = Panel::Dpi;
+        }
+    }
+
+    fn next_panel(&mut self) {
+        let profile = self.profile();
+        self.panel = match self.panel {
+            Panel::Profiles => Panel::Dpi,
+            Panel::Dpi if !profile.report_rates.is_empty() => Panel::ReportRate,
+            Panel::Dpi | Panel::ReportRate if profile.has_sensor_settings() => Panel::Sensor,
+            Panel::Dpi | Panel::ReportRate | Panel::Sensor => Panel::Buttons,
+            Panel::Buttons if !profile.leds.is_empty() => Panel::Leds,
+            Panel::Buttons | Panel::Leds => Panel::Profiles,
+        };
+    }
+
+    fn next_profile(&mut self) {
+        if self.profile + 1 < self.device().profiles.len() {
+            self.select_profile(self.profile + 1);
+        }
+    }
+
+    fn prev_profile(&mut self) {
+        if self.profile > 0 {
+            self.select_profile(self.profile - 1);
         }
     }
 
     fn next_dpi(&mut self) {
         let i = self.dpi_state.selected().unwrap_or(0);
-        if i + 1 < self.device.valid_dpis.len() {
+        if i + 1 < self.profile().resolutions.len() {
             self.dpi_state.select(Some(i + 1));
         }
     }
@@ -89,9 +408,70 @@ impl App {
         }
     }
 
+    fn next_rate(&mut self) {
+        let i = self.rate_state.selected().unwrap_or(0);
+        if i + 1 < self.profile().report_rates.len() {
+            self.rate_state.select(Some(i + 1));
+        }
+    }
+
+    fn prev_rate(&mut self) {
+        let i = self.rate_state.selected().unwrap_or(0);
+        if i > 0 {
+            self.rate_state.select(Some(i - 1));
+        }
+    }
+
+    fn selected_rate(&self) -> u32 {
+        self.rate_state
+            .selected()
+            .and_then(|i| self.profile().report_rates.get(i).copied())
+            .unwrap_or(self.profile().report_rate)
+    }
+
+    /// Sensor settings the current profile supports, in display order.
+    fn sensor_rows(&self) -> Vec<SensorRow> {
+        let profile = self.profile();
+        let mut rows = Vec::new();
+        if profile.angle_snapping.is_some() {
+            rows.push(SensorRow::AngleSnapping);
+        }
+        if profile.debounce.is_some() {
+            rows.push(SensorRow::Debounce);
+        }
+        rows
+    }
+
+    fn next_sensor(&mut self) {
+        let i = self.sensor_state.selected().unwrap_or(0);
+        if i + 1 < self.sensor_rows().len() {
+            self.sensor_state.select(Some(i + 1));
+        }
+    }
+
+    fn prev_sensor(&mut self) {
+        let i = self.sensor_state.selected().unwrap_or(0);
+        if i > 0 {
+            self.sensor_state.select(Some(i - 1));
+        }
+    }
+
+    fn open_debounce_editor(&mut self) {
+        let profile = self.profile();
+        let options = profile.debounces.clone();
+        let selected = options
+            .iter()
+            .position(|&d| Some(d) == profile.debounce)
+            .unwrap_or(0);
+
+        self.debounce_options = options;
+        self.popup_state.select(Some(selected));
+        self.mode = Mode::EditingDebounce;
+    }
+
     fn next_button(&mut self) {
         let i = self.button_state.selected().unwrap_or(0);
-        if i + 1 < self.device.buttons.len() {
+        if i + 1 < self.profile().buttons.len() {
             self.button_state.select(Some(i + 1));
         }
     }
@@ -103,9 +483,386 @@ impl App {
         }
     }
 
+    fn next_led(&mut self) {
+        let i = self.led_state.selected().unwrap_or(0);
+        if i + 1 < self.profile().leds.len() {
+            self.led_state.select(Some(i + 1));
+        }
+    }
+
+    fn prev_led(&mut self) {
+        let i = self.led_state.selected().unwrap_or(0);
+        if i > 0 {
+            self.led_state.select(Some(i - 1));
+        }
+    }
+
+    fn selected_led(&self) -> &MouseLed {
+        &self.profile().leds[self.led_state.selected().unwrap_or(0)]
+    }
+
+    fn next_device(&mut self) {
+        let i = self.device_state.selected().unwrap_or(0);
+        if i + 1 < self.devices.len() {
+            self.device_state.select(Some(i + 1));
+        }
+    }
+
+    fn prev_device(&mut self) {
+        let i = self.device_state.selected().unwrap_or(0);
+        if i > 0 {
+            self.device_state.select(Some(i - 1));
+        }
+    }
+
+    fn open_device_switcher(&mut self) {
+        self.device_state.select(Some(self.device));
+        self.mode = Mode::SelectingDevice;
+    }
+
+    /// Drop any editor, picker or recording in progress and return to the
+    /// main view. Their indices only hold while the device's layout does.
+    fn close_editors(&mut self) {
+        self.key_picker = None;
+        self.key_capture = None;
+        self.capture_echo_until = None;
+        self.macro_editor = None;
+        self.macro_recorder = None;
+        self.recording = None;
+        self.color_picker = None;
+        self.mode = Mode::Normal;
+    }
+
+    /// Take on the devices as just read from ratbagd. Devices already shown
+    /// keep their staged edits and selection; devices plugged in or removed
+    /// since are added or dropped. Changes made outside ratbagtui are
+    /// reported in the status line.
+    fn sync(&mut self, fresh: Vec<MouseDevice>) {
+        let current_path = self.device().device_path.clone();
+        let mut old: Vec<Option<MouseDevice>> = std::mem::take(&mut self.devices).into_iter().map(Some).collect();
+        let mut reverts = std::mem::take(&mut self.reverts);
+        let mut kept = Vec::new();
+        let mut devices = Vec::new();
+        let mut current = None;
+        let mut relaid = false;
+        let mut notices = Vec::new();
+
+        for device in fresh {
+            let known = old
+                .iter()
+                .position(|d| d.as_ref().is_some_and(|d| d.device_path == device.device_path))
+                .or_else(|| old.iter().position(|d| d.as_ref().is_some_and(|d| d.name == device.name)));
+            let Some((i, mut known)) = known.and_then(|i| old[i].take().map(|d| (i, d))) else {
+                notices.push(format!("{} connected", device.name));
+                devices.push(device);
+                continue;
+            };
+            let same_layout = known.same_layout(&device);
+            if i == self.device {
+                current = Some(devices.len());
+                relaid = !same_layout;
+            }
+            // The remap's button indices only hold while the layout does
+            if let Some(r) = reverts.iter().position(|r| r.device_path == known.device_path) {
+                let mut revert = reverts.remove(r);
+                if same_layout {
+                    revert.device_path = device.device_path.clone();
+                    kept.push(revert);
+                } else {
+                    notices.push(format!("Automatic revert of button changes on {} cancelled: the device changed", known.name));
+                }
+            }
+            let external = known.sync(device);
+            if !external.is_empty() {
+                notices.push(format!("{} changed outside ratbagtui: {}", known.name, history::describe(&external)));
+            }
+            devices.push(known);
+        }
+
+        let mut gone: Vec<MouseDevice> = old.into_iter().flatten().collect();
+        notices.extend(
+            reverts
+                .iter()
+                .map(|r| format!("Automatic revert of button changes on {} cancelled: it disconnected", r.name)),
+        );
+        // Nothing is left to confirm for these, so don't quit as if it had been
+        if !reverts.is_empty() {
+            self.jobs.quit_pending = false;
+        }
+        if !self.disconnected && !self.daemon_down {
+            notices.extend(gone.iter().map(|d| format!("{} disconnected", d.name)));
+        }
+
+        match current {
+            Some(index) => {
+                self.devices = devices;
+                self.device = index;
+                self.device_state.select(Some(index));
+                self.disconnected = false;
+                if relaid {
+                    self.history = History::default();
+                    self.close_editors();
+                    self.select_profile(self.profile.min(self.device().profiles.len() - 1));
+                }
+            }
+            // Keep showing the last device until something is plugged in
+            None if devices.is_empty() => {
+                let shown = gone.iter().position(|d| d.device_path == current_path).unwrap_or(0);
+                self.devices = vec![gone.swap_remove(shown)];
+                self.device = 0;
+                self.disconnected = true;
+            }
+            None => {
+                let last = state::load_last_device().and_then(|name| devices.iter().position(|d| d.name == name));
+                let jobs = std::mem::take(&mut self.jobs);
+                *self = App::new(devices, last.unwrap_or(0));
+                self.jobs = jobs;
+            }
+        }
+        self.daemon_down = false;
+
+        self.reverts = kept;
+        if self.reverts.is_empty() {
+            if self.mode == Mode::ConfirmingRemap {
+                self.mode = Mode::Normal;
+            }
+        } else {
+            // Rebuilding the app or the device's layout changing leaves the
+            // mode Normal, but the remaining remaps still need an answer
+            self.mode = Mode::ConfirmingRemap;
+        }
+        if self.mode == Mode::ReviewingChanges {
+            self.pending = self.device().pending_changes();
+            if self.pending.is_empty() {
+                self.mode = Mode::Normal;
+            } else {
+                let i = self.popup_state.selected().unwrap_or(0);
+                self.popup_state.select(Some(i.min(self.pending.len() - 1)));
+            }
+        }
+        if !notices.is_empty() {
+            self.status = Some(notices.join(" · "));
+        }
+    }
+
+    /// Take on a device the worker has written. If the write remapped a
+    /// primary button, start the countdown after which it is reverted unless
+    /// confirmed. Each device written gets its own countdown.
+    fn written(&mut self, device: MouseDevice, changes: usize, risky: Vec<Edit>) {
+        let Some(index) = self.devices.iter().position(|d| d.device_path == device.device_path) else {
+            return;
+        };
+        let name = device.name.clone();
+        self.devices[index] = device;
+        if changes == 0 {
+            return;
+        }
+        self.status = Some(format!(
+            "Wrote {} change{} to {}",
+            changes,
+            if changes == 1 { "" } else { "s" },
+            name
+        ));
+
+        if !risky.is_empty() {
+            self.reverts.push(PendingRevert {
+                device_path: self.devices[index].device_path.clone(),
+                name,
+                edits: risky,
+                deadline: Instant::now() + REVERT_TIMEOUT,
+            });
+            self.mode = Mode::ConfirmingRemap;
+            // Keys typed before the question was asked mustn't answer it
+            self.jobs.queued_keys.clear();
+        }
+    }
+
+    /// Handle the worker's answer to the job `id`. Returns whether reading
+    /// the devices should be tried again shortly.
+    fn finish_job(&mut self, id: u64, result: Result<Outcome, RatbagError>) -> bool {
+        let Some(task) = self.jobs.finish(id) else {
+            return false;
+        };
+        match (task, result) {
+            (Task::Load, Ok(Outcome::Loaded(mut devices))) => {
+                devices.retain(|d| !d.profiles.is_empty());
+                self.sync(devices);
+            }
+            // Probably still starting up
+            (Task::Load, Err(_)) if self.daemon_down => return true,
+            (Task::Load, Err(e)) => self.status = Some(error_status("Error reading devices", &e)),
+            (Task::Write { risky, .. }, Ok(Outcome::Written { device, changes })) => {
+                self.written(device, changes, risky)
+            }
+            (Task::Write { name, .. }, Err(e)) => {
+                self.status = Some(error_status(&format!("Error writing {}", name), &e));
+                self.jobs.quit_pending = false;
+            }
+            (Task::Revert { .. }, Ok(Outcome::Written { device, .. })) => {
+                self.written(device, 0, Vec::new());
+                self.status = Some("Button changes reverted".into());
+            }
+            (Task::Revert { .. }, Err(e)) => {
+                self.status = Some(error_status("Error reverting button changes", &e))
+            }
+            (_, Ok(_)) => {}
+        }
+        false
+    }
+
+    /// Note what ratbagd reported and return when to read the devices again,
+    /// once it has been quiet for a moment, or `None` not to.
+    fn device_event(&mut self, event: DeviceEvent) -> Option<Instant> {
+        match event {
+            DeviceEvent::DaemonStopped => {
+                self.daemon_down = true;
+                self.status = Some("ratbagd stopped; waiting for it to come back".into());
+                None
+            }
+            DeviceEvent::DaemonStarted => Some(Instant::now() + DAEMON_START_DELAY),
+            DeviceEvent::Changed | DeviceEvent::Resync => Some(Instant::now() + SYNC_DELAY),
+        }
+    }
+
+    /// Show a click from the mouse in test mode, with what it's mapped to.
+    fn show_click(&mut self, code: u16) {
+        let label = match code {
+            1 => "Left Click".into(),
+            2 => "Right Click".into(),
+            3 => "Middle Click".into(),
+            4 => "Back".into(),
+            5 => "Forward".into(),
+            _ => format!("Button {}", code),
+        };
+
+        let mapped = self
+            .profile()
+            .buttons
+            .iter()
+            .find(|b| matches!(&b.action, ButtonAction::Button(n) if *n == code as u32))
+            .map(|b| format!("  →  mapped as: {}", b.action.label()))
+            .unwrap_or_default();
+
+        self.last_input = Some(format!("{}{}", label, mapped));
+    }
+
+    /// When the screen next needs redrawing without any input: the next
+    /// device read, and regular ticks while a countdown or spinner is shown.
+    fn next_wake(&self, sync_due: Option<Instant>) -> Option<Instant> {
+        let animating = !self.reverts.is_empty() || !self.jobs.in_flight.is_empty();
+        let tick = animating.then(|| Instant::now() + ANIMATION_TICK);
+        let deadline = self.reverts.iter().map(|r| r.deadline).min();
+        [sync_due, tick, deadline].into_iter().flatten().min()
+    }
+
+    /// Stage an edit to the current device and record it for undo.
+    fn edit(&mut self, f: impl FnOnce(&mut MouseDevice)) {
+        let before = self.device().profiles.clone();
+        f(self.device_mut());
+        let step = dbus::device::diff(&before, &self.device().profiles);
+        self.history.record(step);
+    }
+
+    fn undo(&mut self) {
+        let Some(step) = self.history.undo() else {
+            self.status = Some("Nothing to undo".into());
+            return;
+        };
+        let step = step.to_vec();
+        for edit in step.iter().rev() {
+            self.device_mut().stage(&edit.before);
+        }
+        self.status = Some(format!("Undid {}", history::describe(&step)));
+    }
+
+    fn redo(&mut self) {
+        let Some(step) = self.history.redo() else {
+            self.status = Some("Nothing to redo".into());
+            return;
+        };
+        let step = step.to_vec();
+        for edit in &step {
+            self.device_mut().stage(&edit.after);
+        }
+        self.status = Some(format!("Redid {}", history::describe(&step)));
+    }
+
+    /// Open the pending-changes view for the current device.
+    fn open_pending_changes(&mut self) {
+        self.pending = self.device().pending_changes();
+        if self.pending.is_empty() {
+            self.status = Some("No unsaved changes".into());
+            return;
+        }
+        self.popup_state.select(Some(0));
+        self.mode = Mode::ReviewingChanges;
+    }
+
+    /// Stage the settings file at `import_path` on the current device and show
+    /// what it would change, so nothing is written until confirmed.
+    fn import_config(&mut self) {
+        self.mode = Mode::Normal;
+        let path = match self.import_path.strip_prefix("~/") {
+            Some(rest) => std::env::var_os("HOME").map_or_else(|| rest.into(), |home| PathBuf::from(home).join(rest)),
+            None => PathBuf::from(&self.import_path),
+        };
+        let config = match DeviceConfig::load(&path) {
+            Ok(config) => config,
+            Err(e) => {
+                self.status = Some(format!("Error: {}", e));
+                return;
+            }
+        };
+        let mut result = Ok(());
+        self.edit(|d| result = config.stage(d));
+        if let Err(e) = result {
+            self.status = Some(format!("Error: {}", e));
+            return;
+        }
+        self.open_pending_changes();
+        self.status = Some(if self.mode != Mode::ReviewingChanges {
+            format!("{} already matches {}", self.device().name, path.display())
+        } else if config.name != self.device().name {
+            format!("Staged {} (written for {})", path.display(), config.name)
+        } else {
+            format!("Staged {}", path.display())
+        });
+    }
+
+    /// Put the selected pending change back to its saved value.
+    fn discard_pending_change(&mut self) {
+        let i = self.popup_state.selected().unwrap_or(0);
+        let Some(change) = self.pending.get(i).cloned() else {
+            return;
+        };
+        self.edit(|d| d.stage(&change.before));
+        self.pending = self.device().pending_changes();
+        if self.pending.is_empty() {
+            self.mode = Mode::Normal;
+        } else {
+            self.popup_state.select(Some(i.min(self.pending.len() - 1)));
+        }
+        self.status = Some(format!("Discarded {}", change.after.target()));
+    }
+
+    /// Number of staged changes across every device.
+    fn unsaved_changes(&self) -> usize {
+        self.devices.iter().map(|d| d.pending_changes().len()).sum()
+    }
+
+    fn popup_len(&self) -> usize {
+        match self.mode {
+            Mode::EditingDpi => self.dpi_options.len(),
+            Mode::EditingLed => self.led_options.len(),
+            Mode::EditingDebounce => self.debounce_options.len(),
+            Mode::ReviewingChanges => self.pending.len(),
+            _ => self.popup_options.len(),
+        }
+    }
+
     fn next_popup(&mut self) {
         let i = self.popup_state.selected().unwrap_or(0);
-        if i + 1 < self.popup_options.len() {
+        if i + 1 < self.popup_len() {
             self.popup_state.select(Some(i + 1));
         }
     }
@@ -117,44 +874,300 @@ impl App {
         }
     }
 
-    fn selected_dpi(&self) -> u32 {
-        self.dpi_state
-            .selected()
-            .map(|i| self.device.valid_dpis[i])
-            .unwrap_or(self.device.dpi)
+    fn selected_resolution(&self) -> &MouseResolution {
+        &self.profile().resolutions[self.dpi_state.selected().unwrap_or(0)]
+    }
+
+    fn open_dpi_editor(&mut self) {
+        let res = self.selected_resolution();
+        let dpi = res.dpi;
+        let options = res.valid_dpis.clone();
+        let selected = options.iter().position(|&d| d == dpi.x()).unwrap_or(0);
+
+        self.dpi_options = options;
+        self.dpi_edit = dpi;
+        self.dpi_axis = Axis::X;
+        self.dpi_lock = dpi.x() == dpi.y();
+        self.popup_state.select(Some(selected));
+        self.mode = Mode::EditingDpi;
+    }
+
+    fn select_dpi_axis(&mut self, axis: Axis) {
+        if !self.dpi_edit.is_separate() || self.dpi_lock {
+            return;
+        }
+        self.dpi_axis = axis;
+        let current = match axis {
+            Axis::X => self.dpi_edit.x(),
+            Axis::Y => self.dpi_edit.y(),
+        };
+        if let Some(i) = self.dpi_options.iter().position(|&d| d == current) {
+            self.popup_state.select(Some(i));
+        }
+    }
+
+    fn toggle_dpi_lock(&mut self) {
+        if self.dpi_edit.is_separate() {
+            self.dpi_lock = !self.dpi_lock;
+            self.dpi_axis = Axis::X;
+        }
+    }
+
+    /// Feed the highlighted value into the DPI popup. Returns the resolution
+    /// to write once every axis has been chosen.
+    fn choose_dpi(&mut self) -> Option<Dpi> {
+        let value = *self.dpi_options.get(self.popup_state.selected().unwrap_or(0))?;
+        match self.dpi_edit {
+            Dpi::Unified(_) => Some(Dpi::Unified(value)),
+            Dpi::Separate { .. } if self.dpi_lock => Some(Dpi::Separate { x: value, y: value }),
+            Dpi::Separate { y, .. } if self.dpi_axis == Axis::X => {
+                self.dpi_edit = Dpi::Separate { x: value, y };
+                self.select_dpi_axis(Axis::Y);
+                None
+            }
+            Dpi::Separate { x, .. } => Some(Dpi::Separate { x, y: value }),
+        }
     }
 
     fn open_button_editor(&mut self) {
-        let mut options = vec![ButtonAction::None];
-        for n in 1u32..=8 {
-            options.push(ButtonAction::Button(n));
+        let current = self.button_state.selected().unwrap_or(0);
+        let button = &self.profile().buttons[current];
+        let tabs = button.categories();
+        if tabs.is_empty() {
+            self.status = Some(format!("Button {} cannot be remapped", button.index));
+            return;
         }
 
+        let tab = button
+            .action
+            .category()
+            .and_then(|c| tabs.iter().position(|&t| t == c))
+            .unwrap_or(0);
+
+        self.action_tabs = tabs;
+        self.action_tab = tab;
+        self.load_action_tab();
+        self.mode = Mode::EditingButton;
+    }
+
+    /// Fill the editor list with the actions of the current tab.
+    fn load_action_tab(&mut self) {
         let current = self.button_state.selected().unwrap_or(0);
-        let current_action = &self.device.buttons[current].action;
+        let button = &self.profile().buttons[current];
+
+        let mut options = Vec::new();
+        match self.action_tabs[self.action_tab] {
+            ActionCategory::Button => {
+                if button.supports_none() {
+                    options.push(ButtonAction::None);
+                }
+                for n in 1u32..=8 {
+                    options.push(ButtonAction::Button(n));
+                }
+            }
+            ActionCategory::Special => {
+                for &(n, _) in SPECIAL_ACTIONS {
+                    options.push(ButtonAction::Special(n));
+                }
+            }
+            ActionCategory::Key => options.push(ButtonAction::Key(0)),
+            ActionCategory::Macro => options.push(ButtonAction::Macro(Vec::new())),
+        }
+
+        let current_action = &button.action;
         let selected = options
             .iter()
             .position(|o| match (o, current_action) {
                 (ButtonAction::None, ButtonAction::None) => true,
                 (ButtonAction::Button(a), ButtonAction::Button(b)) => a == b,
+                (ButtonAction::Special(a), ButtonAction::Special(b)) => a == b,
                 _ => false,
             })
             .unwrap_or(0);
 
         self.popup_options = options;
         self.popup_state.select(Some(selected));
-        self.mode = Mode::EditingButton;
     }
-}
 
-fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
-    let x = area.x + area.width.saturating_sub(width) / 2;
-    let y = area.y + area.height.saturating_sub(height) / 2;
-    Rect::new(x, y, width.min(area.width), height.min(area.height))
-}
+    fn next_action_tab(&mut self) {
+        if self.action_tab + 1 < self.action_tabs.len() {
+            self.action_tab += 1;
+            self.load_action_tab();
+        }
+    }
 
-fn ui(frame: &mut Frame, app: &mut App) {
-    let area = frame.area();
+    fn prev_action_tab(&mut self) {
+        if self.action_tab > 0 {
+            self.action_tab -= 1;
+            self.load_action_tab();
+        }
+    }
+
+    /// Open the key picker, either for the selected button or to add a key
+    /// to the macro being edited.
+    fn open_key_picker(&mut self, target: KeyTarget) {
+        let current = match target {
+            KeyTarget::Button => {
+                let button = self.button_state.selected().unwrap_or(0);
+                match self.profile().buttons[button].action {
+                    ButtonAction::Key(n) => Some(n),
+                    _ => None,
+                }
+            }
+            KeyTarget::Macro => None,
+        };
+        self.key_picker = Some(KeyPicker::new(current));
+        self.key_target = target;
+        self.mode = Mode::PickingKey;
+    }
+
+    /// Wait for the next key pressed, read from the keyboard's evdev node if
+    /// one is readable and from the terminal otherwise.
+    fn start_key_capture(&mut self, target: KeyTarget) {
+        let keyboards = input::find_keyboards();
+        self.capture_keyboards = keyboards.len();
+        self.key_capture = if keyboards.is_empty() {
+            None
+        } else {
+            let (tx, rx) = mpsc::channel(1);
+            input::start_key_capture(keyboards, tx);
+            Some(rx)
+        };
+        self.capture_echoed = false;
+        self.key_picker = None;
+        self.key_target = target;
+        self.mode = Mode::CapturingKey;
+    }
+
+    /// Take a key read from evdev in capture mode; Esc on its own cancels.
+    /// The terminal sees the same keystroke, so unless it has already
+    /// arrived its copy is dropped rather than handled in the next mode.
+    fn captured(&mut self, key: CapturedKey) {
+        if key.code == evdev::KeyCode::KEY_ESC.code() as u32 && key.modifiers.is_empty() {
+            self.close_key_picker();
+        } else {
+            self.apply_key(key);
+        }
+        // Modifiers and media keys never reach the terminal, so only wait a moment
+        if !self.capture_echoed {
+            self.capture_echo_until = Some(Instant::now() + CAPTURE_ECHO_WINDOW);
+        }
+    }
+
+    /// Whether a key from the terminal is the copy of one just captured.
+    fn is_capture_echo(&mut self) -> bool {
+        self.capture_echo_until.take().is_some_and(|until| Instant::now() < until)
+    }
+
+    /// Leave the key picker or capture, returning to the macro editor if it
+    /// was opened from there.
+    fn close_key_picker(&mut self) {
+        self.key_picker = None;
+        self.key_capture = None;
+        self.mode = match self.key_target {
+            KeyTarget::Button => Mode::Normal,
+            KeyTarget::Macro => Mode::EditingMacro,
+        };
+    }
+
+    /// Apply a key chosen in the key picker or capture mode to its target,
+    /// then close the picker.
+    fn apply_key(&mut self, key: CapturedKey) {
+        match self.key_target {
+            KeyTarget::Button => {
+                let profile = self.profile;
+                let button_index = self.button_state.selected().unwrap_or(0);
+                let action = ButtonAction::Key(key.code);
+                let label = action.label();
+                self.edit(|d| d.set_button(profile, button_index, action));
+                // A key action is a single keycode; combinations need a macro
+                self.status = Some(if key.modifiers.is_empty() {
+                    format!("Button {} set to {}", button_index, label)
+                } else {
+                    format!(
+                        "Button {} set to {} (modifiers dropped; use a macro for {})",
+                        button_index,
+                        label,
+                        key.label()
+                    )
+                });
+            }
+            KeyTarget::Macro => {
+                if let Some(ref mut editor) = self.macro_editor {
+                    editor.add_key_combo(&key.modifiers, key.code);
+                }
+            }
+        }
+        self.close_key_picker();
+    }
+
+    /// Open the macro editor on the selected button, starting from its
+    /// current macro if it has one.
+    fn open_macro_editor(&mut self) {
+        let current = self.button_state.selected().unwrap_or(0);
+        let steps = match &self.profile().buttons[current].action {
+            ButtonAction::Macro(steps) => steps.clone(),
+            _ => Vec::new(),
+        };
+        self.macro_editor = Some(MacroEditor::new(steps));
+        self.mode = Mode::EditingMacro;
+    }
+
+    /// Start recording a macro for the selected button from the keyboard.
+    fn start_macro_recording(&mut self) {
+        let button = self.button_state.selected().unwrap_or(0);
+        if !self.profile().buttons[button].supports(ActionCategory::Macro) {
+            self.status = Some(format!("Button {} does not support macros", button));
+            return;
+        }
+        let keyboards = input::find_keyboards();
+        if keyboards.is_empty() {
+            self.status = Some("No readable keyboard found (are you in the input group?)".into());
+            return;
+        }
+        let (tx, rx) = mpsc::channel(64);
+        input::start_key_recorder(keyboards, tx);
+        self.macro_recorder = Some(MacroRecorder::new());
+        self.recording = Some(rx);
+        self.mode = Mode::RecordingMacro;
+    }
+
+    fn stop_macro_recording(&mut self) {
+        self.recording = None;
+        if let Some(ref mut recorder) = self.macro_recorder {
+            recorder.finish();
+        }
+    }
+
+    fn open_led_editor(&mut self) {
+        let led = self.selected_led();
+        if led.modes.is_empty() {
+            self.status = Some(format!("LED {} does not report any modes", led.index));
+            return;
+        }
+        let options = led.modes.clone();
+        let selected = options.iter().position(|&m| m == led.mode).unwrap_or(0);
+
+        self.led_options = options;
+        self.popup_state.select(Some(selected));
+        self.mode = Mode::EditingLed;
+    }
+
+    fn open_color_picker(&mut self) {
+        let (r, g, b) = self.selected_led().color;
+        self.color_picker = Some(ColorPicker::new((r.min(255) as u8, g.min(255) as u8, b.min(255) as u8)));
+        self.mode = Mode::PickingColor;
+    }
+}
+
+fn centered_rect(width: u16, height: u16, area: Rect) -> Rect {
+    let x = area.x + area.width.saturating_sub(width) / 2;
+    let y = area.y + area.height.saturating_sub(height) / 2;
+    Rect::new(x, y, width.min(area.width), height.min(area.height))
+}
+
+fn ui(frame: &mut Frame, app: &mut App) {
+    let area = frame.area();
 
     let root = Layout::default()
         .direction(Direction::Vertical)
@@ -166,7 +1179,8 @@ fn ui(frame: &mut Frame, app: &mut App) {
         .split(area);
 
     // Header
-    let header = Paragraph::new(Line::from(vec![
+    let profile = app.profile();
+    let mut header = Line::from(vec![
         Span::styled(
             "ratbagtui",
             Style::default()
@@ -174,30 +1188,112 @@ fn ui(frame: &mut Frame, app: &mut App) {
                 .add_modifier(Modifier::BOLD),
         ),
         Span::raw("  ·  "),
-        Span::styled(&app.device.name, Style::default().fg(Color::White)),
-        Span::raw(format!("  ·  {}dpi", app.device.dpi)),
-    ]))
-    .block(Block::default().borders(Borders::ALL));
+        Span::styled(&app.device().name, Style::default().fg(Color::White)),
+        Span::raw(format!("  ·  {}  ·  {}dpi", profile.label(), profile.dpi().label())),
+    ]);
+    let offline = if app.daemon_down {
+        Some("ratbagd unavailable")
+    } else if app.disconnected {
+        Some("disconnected")
+    } else {
+        None
+    };
+    if let Some(offline) = offline {
+        header.push_span(Span::raw("  ·  "));
+        header.push_span(Span::styled(
+            offline,
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        ));
+    }
+    let unsaved = app.device().pending_changes().len();
+    if unsaved > 0 {
+        header.push_span(Span::raw("  ·  "));
+        header.push_span(Span::styled(
+            format!("{} unsaved", unsaved),
+            Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+        ));
+    }
+    let header = Paragraph::new(header).block(Block::default().borders(Borders::ALL));
     frame.render_widget(header, root[0]);
 
-    // Two panels
+    // Two columns: profiles, DPI, report rate and sensor settings on the
+    // left, buttons and LEDs on the right
     let panels = Layout::default()
         .direction(Direction::Horizontal)
         .constraints([Constraint::Percentage(40), Constraint::Percentage(60)])
         .split(root[1]);
 
-    // DPI panel
-    let dpi_items: Vec<ListItem> = app
-        .device
-        .valid_dpis
+    let rates = &app.profile().report_rates;
+    let sensor_rows = app.sensor_rows();
+    let left = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Length(app.device().profiles.len() as u16 + 2),
+            Constraint::Min(0),
+            Constraint::Length(if rates.is_empty() { 0 } else { rates.len() as u16 + 2 }),
+            Constraint::Length(if sensor_rows.is_empty() { 0 } else { sensor_rows.len() as u16 + 2 }),
+        ])
+        .split(panels[0]);
+
+    // Profile panel
+    let device = &app.devices[app.device];
+    let profile_items: Vec<ListItem> = device
+        .profiles
         .iter()
-        .map(|&d| {
-            let label = if d == app.device.dpi {
-                format!("{} dpi  ←", d)
+        .enumerate()
+        .map(|(i, p)| {
+            let label = if p.is_active {
+                format!("{}  {}  ●", p.index, p.label())
             } else {
-                format!("{} dpi", d)
+                format!("{}  {}", p.index, p.label())
             };
-            ListItem::new(label)
+            ListItem::new(Line::from(vec![Span::raw(label), modified_marker(device.profile_modified(i))]))
+        })
+        .collect();
+
+    let profile_style = if app.panel == Panel::Profiles && app.mode == Mode::Normal {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default().fg(Color::DarkGray)
+    };
+
+    let profile_list = List::new(profile_items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(profile_style)
+                .title(" Profiles "),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::Cyan)
+                .fg(Color::Black)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▶ ");
+    frame.render_stateful_widget(profile_list, left[0], &mut app.profile_state);
+
+    // DPI panel
+    let profile = &app.devices[app.device].profiles[app.profile];
+    let dpi_items: Vec<ListItem> = profile
+        .resolutions
+        .iter()
+        .enumerate()
+        .map(|(i, res)| {
+            let marker = modified_marker(device.resolution_modified(app.profile, i));
+            let mut label = format!("{}  {:>5} dpi", res.index, res.dpi.label());
+            if res.is_active {
+                label.push_str("  ●");
+            }
+            if res.is_default {
+                label.push_str("  ★");
+            }
+            if res.is_disabled {
+                ListItem::new(Line::from(vec![Span::raw(format!("{}  (disabled)", label)), marker]))
+                    .style(Style::default().fg(Color::DarkGray))
+            } else {
+                ListItem::new(Line::from(vec![Span::raw(label), marker]))
+            }
         })
         .collect();
 
@@ -207,90 +1303,768 @@ fn ui(frame: &mut Frame, app: &mut App) {
         Style::default().fg(Color::DarkGray)
     };
 
-    let dpi_list = List::new(dpi_items)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(dpi_style)
-                .title(" DPI "),
-        )
-        .highlight_style(
-            Style::default()
-                .bg(Color::Cyan)
-                .fg(Color::Black)
-                .add_modifier(Modifier::BOLD),
-        )
-        .highlight_symbol("▶ ");
-    frame.render_stateful_widget(dpi_list, panels[0], &mut app.dpi_state);
+    let dpi_list = List::new(dpi_items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(dpi_style)
+                .title(" DPI Stages "),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::Cyan)
+                .fg(Color::Black)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▶ ");
+    frame.render_stateful_widget(dpi_list, left[1], &mut app.dpi_state);
+
+    // Report rate panel
+    if !profile.report_rates.is_empty() {
+        let rate_items: Vec<ListItem> = profile
+            .report_rates
+            .iter()
+            .map(|&r| {
+                if r == profile.report_rate {
+                    ListItem::new(Line::from(vec![
+                        Span::raw(format!("{} Hz  ←", r)),
+                        modified_marker(device.report_rate_modified(app.profile)),
+                    ]))
+                } else {
+                    ListItem::new(format!("{} Hz", r))
+                }
+            })
+            .collect();
+
+        let rate_style = if app.panel == Panel::ReportRate && app.mode == Mode::Normal {
+            Style::default().fg(Color::Cyan)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+
+        let rate_list = List::new(rate_items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(rate_style)
+                    .title(" Report Rate "),
+            )
+            .highlight_style(
+                Style::default()
+                    .bg(Color::Cyan)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol("▶ ");
+        frame.render_stateful_widget(rate_list, left[2], &mut app.rate_state);
+    }
+
+    // Sensor panel
+    if !sensor_rows.is_empty() {
+        let sensor_items: Vec<ListItem> = sensor_rows
+            .iter()
+            .map(|row| match row {
+                SensorRow::AngleSnapping => ListItem::new(Line::from(vec![
+                    Span::raw(format!(
+                        "Angle snapping   {}",
+                        if profile.angle_snapping == Some(true) { "on" } else { "off" }
+                    )),
+                    modified_marker(device.angle_snapping_modified(app.profile)),
+                ])),
+                SensorRow::Debounce => ListItem::new(Line::from(vec![
+                    Span::raw(format!("Debounce         {} ms", profile.debounce.unwrap_or(0))),
+                    modified_marker(device.debounce_modified(app.profile)),
+                ])),
+            })
+            .collect();
+
+        let sensor_style = if app.panel == Panel::Sensor && app.mode == Mode::Normal {
+            Style::default().fg(Color::Cyan)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+
+        let sensor_list = List::new(sensor_items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(sensor_style)
+                    .title(" Sensor "),
+            )
+            .highlight_style(
+                Style::default()
+                    .bg(Color::Cyan)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol("▶ ");
+        frame.render_stateful_widget(sensor_list, left[3], &mut app.sensor_state);
+    }
+
+    // Button panel
+    let button_items: Vec<ListItem> = profile
+        .buttons
+        .iter()
+        .enumerate()
+        .map(|(i, btn)| {
+            ListItem::new(Line::from(vec![
+                Span::raw(format!("Button {}   {}", btn.index, btn.action.label())),
+                modified_marker(device.button_modified(app.profile, i)),
+            ]))
+        })
+        .collect();
+
+    let btn_style = if app.panel == Panel::Buttons && app.mode == Mode::Normal {
+        Style::default().fg(Color::Cyan)
+    } else {
+        Style::default().fg(Color::DarkGray)
+    };
+
+    let button_list = List::new(button_items)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(btn_style)
+                .title(" Buttons "),
+        )
+        .highlight_style(
+            Style::default()
+                .bg(Color::Cyan)
+                .fg(Color::Black)
+                .add_modifier(Modifier::BOLD),
+        )
+        .highlight_symbol("▶ ");
+    let right = Layout::default()
+        .direction(Direction::Vertical)
+        .constraints([
+            Constraint::Min(0),
+            Constraint::Length(if profile.leds.is_empty() { 0 } else { profile.leds.len() as u16 + 2 }),
+        ])
+        .split(panels[1]);
+    frame.render_stateful_widget(button_list, right[0], &mut app.button_state);
+
+    // LED panel
+    if !profile.leds.is_empty() {
+        let led_items: Vec<ListItem> = profile
+            .leds
+            .iter()
+            .enumerate()
+            .map(|(i, led)| {
+                let (r, g, b) = led.color;
+                let mut spans = vec![
+                    Span::styled("■ ", Style::default().fg(Color::Rgb(r as u8, g as u8, b as u8))),
+                    Span::raw(format!(
+                        "LED {}   {}   brightness {}",
+                        led.index,
+                        led.mode.label(),
+                        led.brightness
+                    )),
+                ];
+                if !led.is_monochrome() {
+                    spans.push(Span::raw(format!("   #{:02x}{:02x}{:02x}", r, g, b)));
+                }
+                if led.mode.is_animated() {
+                    spans.push(Span::raw(format!("   {} ms", led.effect_duration)));
+                }
+                spans.push(modified_marker(device.led_modified(app.profile, i)));
+                ListItem::new(Line::from(spans))
+            })
+            .collect();
+
+        let led_style = if app.panel == Panel::Leds && app.mode == Mode::Normal {
+            Style::default().fg(Color::Cyan)
+        } else {
+            Style::default().fg(Color::DarkGray)
+        };
+
+        let led_list = List::new(led_items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(led_style)
+                    .title(" LEDs "),
+            )
+            .highlight_style(
+                Style::default()
+                    .bg(Color::Cyan)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol("▶ ");
+        frame.render_stateful_widget(led_list, right[1], &mut app.led_state);
+    }
+
+    // Footer
+    let footer_text = if app.mode == Mode::ImportingConfig {
+        Line::from(vec![
+            Span::styled("Import settings from: ", Style::default().fg(Color::Yellow)),
+            Span::raw(format!("{}_", app.import_path)),
+            Span::styled("  Enter review  Esc cancel", Style::default().fg(Color::DarkGray)),
+        ])
+        .into()
+    } else if let Some(job) = app.jobs.in_flight.first() {
+        let elapsed = job.started.elapsed();
+        let frame = SPINNER[(elapsed.as_millis() / 100) as usize % SPINNER.len()];
+        let mut line = Line::from(vec![
+            Span::styled(format!("{} ", frame), Style::default().fg(Color::Cyan)),
+            Span::raw(format!("{}… {}s", job.task.label(), elapsed.as_secs())),
+        ]);
+        let more = app.jobs.in_flight.len() - 1;
+        if more > 0 {
+            line.push_span(Span::styled(format!("  (+{} more)", more), Style::default().fg(Color::DarkGray)));
+        }
+        let queued = app.jobs.queued_keys.len();
+        if queued > 0 {
+            let full = if queued >= MAX_QUEUED_KEYS { ", ignoring more" } else { "" };
+            line.push_span(Span::styled(
+                format!("  · {} key{} queued{}", queued, if queued == 1 { "" } else { "s" }, full),
+                Style::default().fg(Color::Yellow),
+            ));
+        }
+        line.into()
+    } else if let Some(ref msg) = app.status {
+        // Any further lines, such as a hint for fixing an error, are dimmed
+        let mut lines = msg.lines();
+        let mut text = Text::from(Line::from(Span::styled(lines.next().unwrap_or_default(), Style::default().fg(Color::Yellow))));
+        text.extend(lines.map(|hint| Line::from(Span::styled(hint, Style::default().fg(Color::DarkGray)))));
+        text
+    } else {
+        Line::from(vec![
+            Span::styled(" Tab ", Style::default().bg(Color::DarkGray)),
+            Span::raw(" switch panel  "),
+            Span::styled(" ↑↓ ", Style::default().bg(Color::DarkGray)),
+            Span::raw(" navigate  "),
+            Span::styled(" Enter ", Style::default().bg(Color::DarkGray)),
+            Span::raw(" apply  "),
+            Span::styled(" w ", Style::default().bg(Color::DarkGray)),
+            Span::raw(" write  "),
+            Span::styled(" i ", Style::default().bg(Color::DarkGray)),
+            Span::raw(" import  "),
+            Span::styled(" d ", Style::default().bg(Color::DarkGray)),
+            Span::raw(" device  "),
+            Span::styled(" t ", Style::default().bg(Color::DarkGray)),
+            Span::raw(" test  "),
+            Span::styled(" q ", Style::default().bg(Color::DarkGray)),
+            Span::raw(" quit"),
+        ])
+        .into()
+    };
+    frame.render_widget(Paragraph::new(footer_text), root[2]);
+
+    // DPI value popup
+    if app.mode == Mode::EditingDpi {
+        let separate = app.dpi_edit.is_separate();
+        let extra = if separate { 2 } else { 0 };
+        let popup_area = centered_rect(36, (app.dpi_options.len() as u16) + 4 + extra, area);
+        frame.render_widget(Clear, popup_area);
+
+        let title = match (separate, app.dpi_lock, app.dpi_axis) {
+            (false, _, _) => " Select DPI ".to_string(),
+            (true, true, _) => " Select DPI (X+Y) ".to_string(),
+            (true, false, Axis::X) => " Select X DPI ".to_string(),
+            (true, false, Axis::Y) => " Select Y DPI ".to_string(),
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(title)
+            .title_alignment(Alignment::Center);
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(extra), Constraint::Min(0)])
+            .split(inner);
+
+        if separate {
+            let axis_style = |axis: Axis| {
+                if !app.dpi_lock && app.dpi_axis == axis {
+                    Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+                } else {
+                    Style::default()
+                }
+            };
+            let axes = Paragraph::new(vec![
+                Line::from(vec![
+                    Span::styled(format!("X {}", app.dpi_edit.x()), axis_style(Axis::X)),
+                    Span::raw("   "),
+                    Span::styled(format!("Y {}", app.dpi_edit.y()), axis_style(Axis::Y)),
+                    Span::raw("   "),
+                    Span::styled(
+                        if app.dpi_lock { "[locked]" } else { "[unlocked]" },
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                ]),
+                Line::from(Span::styled(
+                    "←→ axis  L lock",
+                    Style::default().fg(Color::DarkGray),
+                )),
+            ])
+            .alignment(Alignment::Center);
+            frame.render_widget(axes, rows[0]);
+        }
+
+        let popup_items: Vec<ListItem> = app
+            .dpi_options
+            .iter()
+            .map(|d| ListItem::new(format!("{} dpi", d)))
+            .collect();
+
+        let popup_list = List::new(popup_items)
+            .highlight_style(
+                Style::default()
+                    .bg(Color::Yellow)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol("▶ ");
+        frame.render_stateful_widget(popup_list, rows[1], &mut app.popup_state);
+    }
+
+    // Button edit popup
+    if app.mode == Mode::EditingButton {
+        let popup_area = centered_rect(44, (app.popup_options.len() as u16) + 6, area);
+        frame.render_widget(Clear, popup_area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(" Select Action ")
+            .title_alignment(Alignment::Center);
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(2), Constraint::Min(0)])
+            .split(inner);
+
+        let tabs = Tabs::new(app.action_tabs.iter().map(|t| t.label()))
+            .select(app.action_tab)
+            .highlight_style(Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD));
+        frame.render_widget(tabs, rows[0]);
+
+        let popup_items: Vec<ListItem> = app
+            .popup_options
+            .iter()
+            .map(|a| match a {
+                ButtonAction::Key(_) => ListItem::new("Key…"),
+                ButtonAction::Macro(_) => ListItem::new("Macro…"),
+                _ => ListItem::new(a.label()),
+            })
+            .collect();
+
+        let popup_list = List::new(popup_items)
+            .highlight_style(
+                Style::default()
+                    .bg(Color::Yellow)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol("▶ ");
+        frame.render_stateful_widget(popup_list, rows[1], &mut app.popup_state);
+    }
+
+
+    // Macro editor popup, kept visible underneath the key picker
+    if let Some(ref mut editor) = app.macro_editor {
+        let height = (editor.steps.len().max(1) as u16) + 7;
+        let popup_area = centered_rect(48, height, area);
+        frame.render_widget(Clear, popup_area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(" Edit Macro ")
+            .title_alignment(Alignment::Center);
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(3)])
+            .split(inner);
+
+        if editor.steps.is_empty() {
+            let empty = Paragraph::new(Span::styled(
+                "No steps yet — press a to add a key",
+                Style::default().fg(Color::DarkGray),
+            ))
+            .alignment(Alignment::Center);
+            frame.render_widget(empty, rows[0]);
+        } else {
+            let step_items: Vec<ListItem> = editor
+                .steps
+                .iter()
+                .enumerate()
+                .map(|(i, step)| ListItem::new(format!("{:>2}  {}", i + 1, step.label())))
+                .collect();
+
+            let step_list = List::new(step_items)
+                .highlight_style(
+                    Style::default()
+                        .bg(Color::Yellow)
+                        .fg(Color::Black)
+                        .add_modifier(Modifier::BOLD),
+                )
+                .highlight_symbol("▶ ");
+            frame.render_stateful_widget(step_list, rows[0], &mut editor.state);
+        }
+
+        let footer = match editor.delay_input {
+            Some(ref input) => {
+                vec![
+                    Line::from(""),
+                    Line::from(vec![
+                        Span::styled("Delay (ms): ", Style::default().fg(Color::Yellow)),
+                        Span::raw(format!("{}_", input)),
+                    ]),
+                    Line::from(Span::styled(
+                        "Enter add  Esc cancel",
+                        Style::default().fg(Color::DarkGray),
+                    )),
+                ]
+            }
+            None => vec![
+                Line::from(""),
+                Line::from(Span::styled(
+                    "a key  p press key  w delay  d delete  t ↓/↑  J/K move",
+                    Style::default().fg(Color::DarkGray),
+                )),
+                Line::from(Span::styled(
+                    "Enter save  Esc cancel",
+                    Style::default().fg(Color::DarkGray),
+                )),
+            ],
+        };
+        frame.render_widget(Paragraph::new(footer).alignment(Alignment::Center), rows[1]);
+    }
+
+    // Key picker popup
+    if app.mode == Mode::PickingKey
+        && let Some(ref mut picker) = app.key_picker
+    {
+        let popup_area = centered_rect(40, 20, area);
+        frame.render_widget(Clear, popup_area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(" Select Key ")
+            .title_alignment(Alignment::Center);
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Length(2), Constraint::Min(0), Constraint::Length(1)])
+            .split(inner);
+
+        let search = Paragraph::new(Line::from(vec![
+            Span::styled("Search: ", Style::default().fg(Color::Yellow)),
+            Span::raw(format!("{}_", picker.query)),
+        ]));
+        frame.render_widget(search, rows[0]);
+
+        let key_items: Vec<ListItem> = picker
+            .matches
+            .iter()
+            .map(|(code, name)| ListItem::new(format!("{:<24}{:>4}", name, code)))
+            .collect();
+
+        let key_list = List::new(key_items)
+            .highlight_style(
+                Style::default()
+                    .bg(Color::Yellow)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol("▶ ");
+        frame.render_stateful_widget(key_list, rows[1], &mut picker.state);
+
+        let hint = Paragraph::new(Span::styled(
+            "type to filter  ↑↓ select  Tab press key  Esc cancel",
+            Style::default().fg(Color::DarkGray),
+        ))
+        .alignment(Alignment::Center);
+        frame.render_widget(hint, rows[2]);
+    }
+
+    // Macro recorder popup
+    if app.mode == Mode::RecordingMacro
+        && let Some(ref mut recorder) = app.macro_recorder
+    {
+        let recording = app.recording.is_some();
+        let height = (recorder.steps.len().max(1) as u16) + 7;
+        let popup_area = centered_rect(48, height, area);
+        frame.render_widget(Clear, popup_area);
+
+        let (title, border) = if recording {
+            (" ● Recording Macro ", Color::Red)
+        } else {
+            (" Recorded Macro ", Color::Green)
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(border))
+            .title(title)
+            .title_alignment(Alignment::Center);
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(2)])
+            .split(inner);
+
+        if recorder.steps.is_empty() {
+            let empty = Paragraph::new(Span::styled(
+                if recording {
+                    "Type on your keyboard..."
+                } else {
+                    "Nothing recorded"
+                },
+                Style::default().fg(Color::DarkGray),
+            ))
+            .alignment(Alignment::Center);
+            frame.render_widget(empty, rows[0]);
+        } else {
+            let step_items: Vec<ListItem> = recorder
+                .steps
+                .iter()
+                .enumerate()
+                .map(|(i, step)| ListItem::new(format!("{:>3}  {}", i + 1, step.label())))
+                .collect();
+
+            let highlight = if recording {
+                Style::default().fg(Color::Green).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+                    .bg(Color::Yellow)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD)
+            };
+            let step_list = List::new(step_items).highlight_style(highlight).highlight_symbol("▶ ");
+            frame.render_stateful_widget(step_list, rows[0], &mut recorder.state);
+        }
+
+        let hint = if recording {
+            "Press Esc to stop recording"
+        } else {
+            "[ trim start  ] trim end  r re-record  Enter save  Esc discard"
+        };
+        let footer = Paragraph::new(vec![
+            Line::from(""),
+            Line::from(Span::styled(hint, Style::default().fg(Color::DarkGray))),
+        ])
+        .alignment(Alignment::Center);
+        frame.render_widget(footer, rows[1]);
+    }
+
+    // Key capture popup
+    if app.mode == Mode::CapturingKey {
+        let popup_area = centered_rect(44, 8, area);
+        frame.render_widget(Clear, popup_area);
+
+        let source = match app.capture_keyboards {
+            0 => "Reading from the terminal (US layout)".to_string(),
+            1 => "Reading from the keyboard".to_string(),
+            n => format!("Reading from {} keyboards", n),
+        };
+        let popup = Paragraph::new(vec![
+            Line::from(""),
+            Line::from(Span::styled(
+                "Press the key to map",
+                Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+            )),
+            Line::from(Span::styled(source, Style::default().fg(Color::DarkGray))),
+            Line::from(""),
+            Line::from(Span::styled("Esc cancel", Style::default().fg(Color::DarkGray))),
+        ])
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow))
+                .title(" Capture Key ")
+                .title_alignment(Alignment::Center),
+        );
+        frame.render_widget(popup, popup_area);
+    }
+
+    // LED mode popup
+    if app.mode == Mode::EditingLed {
+        let popup_area = centered_rect(36, (app.led_options.len() as u16) + 4, area);
+        frame.render_widget(Clear, popup_area);
+
+        let popup_items: Vec<ListItem> = app
+            .led_options
+            .iter()
+            .map(|m| ListItem::new(m.label()))
+            .collect();
+
+        let popup_list = List::new(popup_items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::Yellow))
+                    .title(" Select LED Mode ")
+                    .title_alignment(Alignment::Center),
+            )
+            .highlight_style(
+                Style::default()
+                    .bg(Color::Yellow)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol("▶ ");
+        frame.render_stateful_widget(popup_list, popup_area, &mut app.popup_state);
+    }
+
+    // Debounce popup
+    if app.mode == Mode::EditingDebounce {
+        let popup_area = centered_rect(36, (app.debounce_options.len() as u16) + 4, area);
+        frame.render_widget(Clear, popup_area);
+
+        let popup_items: Vec<ListItem> = app
+            .debounce_options
+            .iter()
+            .map(|d| ListItem::new(format!("{} ms", d)))
+            .collect();
+
+        let popup_list = List::new(popup_items)
+            .block(
+                Block::default()
+                    .borders(Borders::ALL)
+                    .border_style(Style::default().fg(Color::Yellow))
+                    .title(" Select Debounce Time ")
+                    .title_alignment(Alignment::Center),
+            )
+            .highlight_style(
+                Style::default()
+                    .bg(Color::Yellow)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol("▶ ");
+        frame.render_stateful_widget(popup_list, popup_area, &mut app.popup_state);
+    }
+
+    // LED colour picker popup
+    if app.mode == Mode::PickingColor
+        && let Some(ref picker) = app.color_picker
+    {
+        let led = &app.devices[app.device].profiles[app.profile].leds
+            [app.led_state.selected().unwrap_or(0)];
+        let popup_area = centered_rect(46, 17, area);
+        frame.render_widget(Clear, popup_area);
 
-    // Button panel
-    let button_items: Vec<ListItem> = app
-        .device
-        .buttons
-        .iter()
-        .map(|btn| {
-            ListItem::new(format!("Button {}   {}", btn.index, btn.action.label()))
-        })
-        .collect();
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(format!(" LED {} Colour ({}) ", led.index, led.color_depth_label()))
+            .title_alignment(Alignment::Center);
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
 
-    let btn_style = if app.panel == Panel::Buttons && app.mode == Mode::Normal {
-        Style::default().fg(Color::Cyan)
-    } else {
-        Style::default().fg(Color::DarkGray)
-    };
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([
+                Constraint::Length(8),
+                Constraint::Length(3),
+                Constraint::Min(0),
+            ])
+            .split(inner);
 
-    let button_list = List::new(button_items)
-        .block(
-            Block::default()
-                .borders(Borders::ALL)
-                .border_style(btn_style)
-                .title(" Buttons "),
-        )
-        .highlight_style(
+        let mut lines = Vec::new();
+        for field in PickerField::ALL {
+            let selected = field == picker.field;
+            let marker = if selected { "▶ " } else { "  " };
+            let style = if selected {
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+            } else {
+                Style::default()
+            };
+            let line = if field == PickerField::Hex {
+                let cursor = if selected { "_" } else { "" };
+                Line::from(vec![
+                    Span::styled(format!("{}{:<4}", marker, field.label()), style),
+                    Span::styled(format!("#{}{}", picker.hex_input, cursor), style),
+                ])
+            } else {
+                let value = picker.value(field);
+                let filled = (value * COLOR_SLIDER_WIDTH / field.max()) as usize;
+                let bar_color = match field {
+                    PickerField::Red => Color::Red,
+                    PickerField::Green => Color::Green,
+                    PickerField::Blue => Color::Blue,
+                    _ => Color::Gray,
+                };
+                Line::from(vec![
+                    Span::styled(format!("{}{:<4}", marker, field.label()), style),
+                    Span::styled("█".repeat(filled), Style::default().fg(bar_color)),
+                    Span::styled(
+                        "░".repeat(COLOR_SLIDER_WIDTH as usize - filled),
+                        Style::default().fg(Color::DarkGray),
+                    ),
+                    Span::styled(format!(" {:>3}", value), style),
+                ])
+            };
+            lines.push(line);
+        }
+        frame.render_widget(Paragraph::new(lines), rows[0]);
+
+        let (r, g, b) = picker.rgb;
+        let (qr, qg, qb) = led.quantize_color((r as u32, g as u32, b as u32));
+        let swatch = Paragraph::new(vec![
+            Line::from(""),
+            Line::from(format!("#{:02x}{:02x}{:02x}", qr, qg, qb)),
+        ])
+        .alignment(Alignment::Center)
+        .style(
             Style::default()
-                .bg(Color::Cyan)
-                .fg(Color::Black)
-                .add_modifier(Modifier::BOLD),
-        )
-        .highlight_symbol("▶ ");
-    frame.render_stateful_widget(button_list, panels[1], &mut app.button_state);
+                .bg(Color::Rgb(qr as u8, qg as u8, qb as u8))
+                .fg(if qr * 299 + qg * 587 + qb * 114 > 128_000 { Color::Black } else { Color::White }),
+        );
+        frame.render_widget(swatch, rows[1]);
 
-    // Footer
-    let footer_text = if let Some(ref msg) = app.status {
-        Line::from(Span::styled(msg, Style::default().fg(Color::Yellow)))
-    } else {
-        Line::from(vec![
-            Span::styled(" Tab ", Style::default().bg(Color::DarkGray)),
-            Span::raw(" switch panel  "),
-            Span::styled(" ↑↓ ", Style::default().bg(Color::DarkGray)),
-            Span::raw(" navigate  "),
-            Span::styled(" Enter ", Style::default().bg(Color::DarkGray)),
-            Span::raw(" apply  "),
-            Span::styled(" t ", Style::default().bg(Color::DarkGray)),
-            Span::raw(" test  "),
-            Span::styled(" q ", Style::default().bg(Color::DarkGray)),
-            Span::raw(" quit"),
+        let hint = Paragraph::new(vec![
+            Line::from(""),
+            Line::from(Span::styled(
+                "←→ adjust (Shift ×16)  Enter apply  Esc cancel",
+                Style::default().fg(Color::DarkGray),
+            )),
         ])
-    };
-    frame.render_widget(Paragraph::new(footer_text), root[2]);
+        .alignment(Alignment::Center);
+        frame.render_widget(hint, rows[2]);
+    }
 
-    // Button edit popup
-    if app.mode == Mode::EditingButton {
-        let popup_area = centered_rect(36, (app.popup_options.len() as u16) + 4, area);
+    // Device switcher popup
+    if app.mode == Mode::SelectingDevice {
+        let popup_area = centered_rect(50, (app.devices.len() as u16) + 4, area);
         frame.render_widget(Clear, popup_area);
 
-        let popup_items: Vec<ListItem> = app
-            .popup_options
+        let device_items: Vec<ListItem> = app
+            .devices
             .iter()
-            .map(|a| ListItem::new(a.label()))
+            .enumerate()
+            .map(|(i, d)| {
+                let label = if i == app.device {
+                    format!("{}  ←", d.name)
+                } else {
+                    d.name.clone()
+                };
+                ListItem::new(label)
+            })
             .collect();
 
-        let popup_list = List::new(popup_items)
+        let device_list = List::new(device_items)
             .block(
                 Block::default()
                     .borders(Borders::ALL)
                     .border_style(Style::default().fg(Color::Yellow))
-                    .title(" Select Action ")
+                    .title(" Select Device ")
                     .title_alignment(Alignment::Center),
             )
             .highlight_style(
@@ -300,7 +2074,131 @@ fn ui(frame: &mut Frame, app: &mut App) {
                     .add_modifier(Modifier::BOLD),
             )
             .highlight_symbol("▶ ");
-        frame.render_stateful_widget(popup_list, popup_area, &mut app.popup_state);
+        frame.render_stateful_widget(device_list, popup_area, &mut app.device_state);
+    }
+
+    // Pending changes popup
+    if app.mode == Mode::ReviewingChanges {
+        let popup_area = centered_rect(64, (app.pending.len() as u16) + 5, area);
+        frame.render_widget(Clear, popup_area);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .border_style(Style::default().fg(Color::Yellow))
+            .title(format!(" Unsaved Changes ({}) ", app.pending.len()))
+            .title_alignment(Alignment::Center);
+        let inner = block.inner(popup_area);
+        frame.render_widget(block, popup_area);
+
+        let rows = Layout::default()
+            .direction(Direction::Vertical)
+            .constraints([Constraint::Min(0), Constraint::Length(2)])
+            .split(inner);
+
+        let change_items: Vec<ListItem> = app
+            .pending
+            .iter()
+            .map(|c| {
+                ListItem::new(Line::from(vec![
+                    Span::raw(format!("{}: ", c.after.target())),
+                    Span::styled(c.before.value_label(), Style::default().fg(Color::Red)),
+                    Span::raw(" → "),
+                    Span::styled(c.after.value_label(), Style::default().fg(Color::Green)),
+                ]))
+            })
+            .collect();
+        let change_list = List::new(change_items)
+            .highlight_style(
+                Style::default()
+                    .bg(Color::Yellow)
+                    .fg(Color::Black)
+                    .add_modifier(Modifier::BOLD),
+            )
+            .highlight_symbol("▶ ");
+        frame.render_stateful_widget(change_list, rows[0], &mut app.popup_state);
+
+        let hint = Paragraph::new(vec![
+            Line::from(""),
+            Line::from(Span::styled(
+                "w/Enter write to mouse  d discard change  x discard all  Esc back",
+                Style::default().fg(Color::DarkGray),
+            )),
+        ])
+        .alignment(Alignment::Center);
+        frame.render_widget(hint, rows[1]);
+    }
+
+    // Remap confirmation popup
+    if app.mode == Mode::ConfirmingRemap
+        && let Some(revert) = app.reverts.first()
+    {
+        let popup_area = centered_rect(56, (revert.edits.len() as u16) + 9, area);
+        frame.render_widget(Clear, popup_area);
+
+        let remaining = revert.deadline.saturating_duration_since(Instant::now());
+        let mut content = vec![
+            Line::from(""),
+            Line::from(Span::styled(
+                "Keep these changes?",
+                Style::default().fg(Color::White).add_modifier(Modifier::BOLD),
+            )),
+            Line::from(Span::styled(revert.name.as_str(), Style::default().fg(Color::Cyan))),
+            Line::from(""),
+        ];
+        content.extend(revert.edits.iter().map(|e| Line::from(e.label())));
+        content.push(Line::from(""));
+        content.push(Line::from(Span::styled(
+            format!("Reverting in {}s", remaining.as_secs() + 1),
+            Style::default().fg(Color::Red).add_modifier(Modifier::BOLD),
+        )));
+        content.push(Line::from(Span::styled(
+            "y/Enter keep  n/Esc revert now",
+            Style::default().fg(Color::DarkGray),
+        )));
+        if app.reverts.len() > 1 {
+            content.push(Line::from(Span::styled(
+                format!("{} more device(s) to confirm after this", app.reverts.len() - 1),
+                Style::default().fg(Color::DarkGray),
+            )));
+        }
+
+        let popup = Paragraph::new(content).alignment(Alignment::Center).block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Red))
+                .title(" Confirm Button Changes ")
+                .title_alignment(Alignment::Center),
+        );
+        frame.render_widget(popup, popup_area);
+    }
+
+    // Quit confirmation popup
+    if app.mode == Mode::ConfirmingQuit {
+        let popup_area = centered_rect(50, 7, area);
+        frame.render_widget(Clear, popup_area);
+
+        let unsaved = app.unsaved_changes();
+        let popup = Paragraph::new(vec![
+            Line::from(""),
+            Line::from(Span::styled(
+                format!("{} unsaved change{}", unsaved, if unsaved == 1 { "" } else { "s" }),
+                Style::default().fg(Color::Yellow).add_modifier(Modifier::BOLD),
+            )),
+            Line::from(""),
+            Line::from(Span::styled(
+                "w write and quit  q quit without writing  Esc cancel",
+                Style::default().fg(Color::DarkGray),
+            )),
+        ])
+        .alignment(Alignment::Center)
+        .block(
+            Block::default()
+                .borders(Borders::ALL)
+                .border_style(Style::default().fg(Color::Yellow))
+                .title(" Quit ")
+                .title_alignment(Alignment::Center),
+        );
+        frame.render_widget(popup, popup_area);
     }
 
     // Test mode popup
@@ -356,18 +2254,167 @@ fn ui(frame: &mut Frame, app: &mut App) {
     }
 }
 
+/// Hand the staged changes of `device` to the worker to write to the mouse.
+/// See [`App::written`] for what happens once they are.
+fn write_device(app: &mut App, worker: &mut Worker, device: usize) -> Result<(), RatbagError> {
+    if app.daemon_down {
+        return Err(RatbagError::DaemonNotRunning);
+    }
+    if app.disconnected {
+        return Err(RatbagError::Disconnected);
+    }
+    let target = &app.devices[device];
+    let changes = target.pending_changes();
+    if changes.is_empty() {
+        return Ok(());
+    }
+    let risky = changes.into_iter().filter(|e| e.touches_primary_button()).collect();
+    let task = Task::Write {
+        name: target.name.clone(),
+        risky,
+    };
+    let id = worker.submit(Job::Write(target.clone()));
+    app.jobs.start(id, task);
+    Ok(())
+}
+
+/// Ask the worker to read the devices again after ratbagd reported changes.
+fn sync_devices(app: &mut App, worker: &mut Worker) {
+    let id = worker.submit(Job::Load);
+    app.jobs.start(id, Task::Load);
+}
+
+/// Receive from `rx`, or wait forever when there is nothing to receive from.
+async fn recv_from<T>(rx: &mut Option<mpsc::Receiver<T>>) -> Option<T> {
+    match rx {
+        Some(rx) => rx.recv().await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Sleep until `deadline`, or forever without one.
+async fn sleep_until(deadline: Option<Instant>) {
+    match deadline {
+        Some(deadline) => tokio::time::sleep_until(deadline.into()).await,
+        None => std::future::pending().await,
+    }
+}
+
+/// Wait until ratbagd is running and has at least one device to show,
+/// checking again whenever it reports a change or starts.
+async fn wait_for_devices(conn: &Connection, events: &mut mpsc::Receiver<DeviceEvent>) -> Vec<MouseDevice> {
+    let mut waiting_for = None;
+    loop {
+        let (message, hint) = match MouseDevice::load(conn).await {
+            Ok(mut devices) => {
+                devices.retain(|d| !d.profiles.is_empty());
+                if !devices.is_empty() {
+                    return devices;
+                }
+                ("No supported devices found; waiting for one to be connected".to_string(), None)
+            }
+            Err(RatbagError::DaemonNotRunning) => (
+                "ratbagd is not running; waiting for it to start".to_string(),
+                RatbagError::DaemonNotRunning.hint(),
+            ),
+            Err(e) => (format!("Can't read devices: {}; trying again", e), e.hint()),
+        };
+        if waiting_for.as_ref() != Some(&message) {
+            eprintln!("{} (Ctrl-C to quit)", message);
+            if let Some(hint) = hint {
+                eprintln!("hint: {}", hint);
+            }
+            waiting_for = Some(message);
+        }
+        // Poll as well, in case ratbagd started before the watcher did
+        match tokio::time::timeout(WAIT_POLL_INTERVAL, events.recv()).await {
+            Ok(Some(_)) => {
+                tokio::time::sleep(SYNC_DELAY).await;
+                while events.try_recv().is_ok() {}
+            }
+            // The watcher isn't running
+            Ok(None) => tokio::time::sleep(WAIT_POLL_INTERVAL).await,
+            Err(_) => {}
+        }
+    }
+}
+
+/// Put back the button mappings from before the unconfirmed remap `index`
+/// and have the worker write them to the mouse. A reverted remap also calls
+/// off quitting.
+fn revert_remap(app: &mut App, worker: &mut Worker, index: usize) {
+    let revert = app.reverts.remove(index);
+    app.jobs.quit_pending = false;
+    if app.reverts.is_empty() && app.mode == Mode::ConfirmingRemap {
+        app.mode = Mode::Normal;
+    }
+    let Some(device) = app.devices.iter_mut().find(|d| d.device_path == revert.device_path) else {
+        return;
+    };
+    for edit in &revert.edits {
+        if let Change::Button { profile, button, ref action } = edit.before {
+            device.set_button(profile, button, action.clone());
+        }
+    }
+    let task = Task::Revert {
+        name: device.name.clone(),
+    };
+    let id = worker.submit(Job::Write(device.clone()));
+    app.jobs.start(id, task);
+}
+
+/// Status line for an error, with the hint for fixing it on the line below.
+fn error_status(context: &str, e: &RatbagError) -> String {
+    match e.hint() {
+        Some(hint) => format!("{}: {}\nHint: {}", context, e, hint),
+        None => format!("{}: {}", context, e),
+    }
+}
+
+/// Marker appended to rows with staged changes that haven't been written.
+fn modified_marker(modified: bool) -> Span<'static> {
+    if modified {
+        Span::styled("  ✎", Style::default().fg(Color::Yellow))
+    } else {
+        Span::raw("")
+    }
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    let conn = Connection::system().await?;
-    let devices = MouseDevice::load(&conn).await?;
+    let args: Vec<String> = std::env::args().skip(1).collect();
+    let options = match cli::tui_options(&args) {
+        Some(Ok(options)) => options,
+        Some(Err(e)) => {
+            eprintln!("ratbagtui: {}", e);
+            std::process::exit(cli::EXIT_USAGE);
+        }
+        None => {
+            if let Err(e) = cli::run(&args).await {
+                cli::report(e.as_ref());
+                std::process::exit(cli::exit_code(e.as_ref()));
+            }
+            return Ok(());
+        }
+    };
 
-    if devices.is_empty() {
-        eprintln!("No devices found. Is ratbagd running?");
-        return Ok(());
-    }
+    let conn = match Connection::system().await {
+        Ok(conn) => conn,
+        Err(e) => {
+            cli::report(&RatbagError::from(e));
+            std::process::exit(cli::EXIT_FAILURE);
+        }
+    };
+    let (sync_tx, mut sync_rx) = mpsc::channel::<DeviceEvent>(64);
+    let watching = dbus::watch::start_watcher(&conn, sync_tx).await;
+    let devices = wait_for_devices(&conn, &mut sync_rx).await;
 
-    let device = devices.into_iter().next().unwrap();
-    let mut app = App::new(device);
+    let last_device = state::load_last_device()
+        .and_then(|name| devices.iter().position(|d| d.name == name));
+    let mut app = App::new(devices, last_device.unwrap_or(0));
+    if last_device.is_none() && app.devices.len() > 1 {
+        app.open_device_switcher();
+    }
 
     enable_raw_mode()?;
     let mut stdout = io::stdout();
@@ -375,110 +2422,605 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let backend = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
 
+    let (mut worker, mut responses) = Worker::spawn(conn.clone(), options.timeout);
     let (tx, mut rx) = mpsc::channel::<u16>(32);
     let input_device = input::find_mouse_device();
+    // Reads clicks from hidraw while in test mode
+    let mut hidraw_listener: Option<tokio::task::JoinHandle<()>> = None;
+
+    if let Err(e) = watching {
+        app.status = Some(format!("Live updates unavailable: {}", e));
+    }
+    let mut sync_due: Option<Instant> = None;
+
+    let mut terminal_events = EventStream::new();
+    let mut dirty = true;
 
     loop {
-        terminal.draw(|f| ui(f, &mut app))?;
+        if app.jobs.quit_pending && !app.jobs.writing() && app.reverts.is_empty() {
+            break;
+        }
+        // A device list change can end test mode without Esc
+        if app.mode != Mode::Testing
+            && let Some(listener) = hidraw_listener.take()
+        {
+            listener.abort();
+        }
 
-        // Check for input events from evdev
-        if let Ok(code) = rx.try_recv() {
-        let label = match code {
-            1 => "Left Click".into(),
-            2 => "Right Click".into(),
-            3 => "Middle Click".into(),
-            4 => "Back".into(),
-            5 => "Forward".into(),
-            _ => format!("Button {}", code),
-        };
+        // Handle every key already waiting before drawing again
+        let Some(key) = app.jobs.next_key() else {
+            if dirty {
+                terminal.draw(|f| ui(f, &mut app))?;
+                dirty = false;
+            }
 
-        let mapped = app
-            .device
-            .buttons
-            .iter()
-            .find(|b| matches!(&b.action, ButtonAction::Button(n) if *n == code as u32))
-            .map(|b| format!("  →  mapped as: {}", b.action.label()))
-            .unwrap_or_default();
+            let wake = app.next_wake(sync_due);
+            tokio::select! {
+                event = terminal_events.next() => {
+                    let Some(event) = event else {
+                        break;
+                    };
+                    dirty |= app.jobs.push_event(event?);
+                    while let Some(Some(event)) = future::poll_once(terminal_events.next()).await {
+                        dirty |= app.jobs.push_event(event?);
+                    }
+                }
+                Some(code) = rx.recv() => {
+                    app.show_click(code);
+                    while let Ok(code) = rx.try_recv() {
+                        app.show_click(code);
+                    }
+                    dirty = true;
+                }
+                Some(event) = sync_rx.recv() => {
+                    sync_due = app.device_event(event);
+                    while let Ok(event) = sync_rx.try_recv() {
+                        sync_due = app.device_event(event);
+                    }
+                    dirty = true;
+                }
+                Some(response) = responses.recv() => {
+                    let mut retry = app.finish_job(response.id, response.result);
+                    while let Ok(response) = responses.try_recv() {
+                        retry |= app.finish_job(response.id, response.result);
+                    }
+                    if retry {
+                        sync_due = Some(Instant::now() + DAEMON_START_DELAY);
+                    }
+                    dirty = true;
+                }
+                // A key read from the keyboard in capture mode
+                Some(captured) = recv_from(&mut app.key_capture) => {
+                    app.captured(captured);
+                    dirty = true;
+                }
+                Some((step, time)) = recv_from(&mut app.recording) => {
+                    if let Some(ref mut recorder) = app.macro_recorder {
+                        recorder.push(step, time);
+                        while let Some(Ok((step, time))) = app.recording.as_mut().map(|r| r.try_recv()) {
+                            recorder.push(step, time);
+                        }
+                    }
+                    dirty = true;
+                }
+                () = sleep_until(wake) => dirty = true,
+            }
 
-        app.last_input = Some(format!("{}{}", label, mapped));
+            let now = Instant::now();
+            if sync_due.is_some_and(|due| now >= due) {
+                sync_due = None;
+                sync_devices(&mut app, &mut worker);
+            }
+            while let Some(i) = app.reverts.iter().position(|r| now >= r.deadline) {
+                revert_remap(&mut app, &mut worker, i);
+            }
+            continue;
+        };
+        dirty = true;
+        if app.is_capture_echo() {
+            continue;
         }
 
-        if event::poll(std::time::Duration::from_millis(50))? {
-            if let Event::Key(key) = event::read()? {
-                match app.mode {
-                    Mode::Normal => match key.code {
-                        KeyCode::Char('q') => break,
+        match app.mode {
+            Mode::Normal => match key.code {
+                KeyCode::Char('q') => {
+                    if app.unsaved_changes() == 0 {
+                        break;
+                    }
+                    app.mode = Mode::ConfirmingQuit;
+                }
+
+                KeyCode::Char('w') => app.open_pending_changes(),
+
+                KeyCode::Char('u') => app.undo(),
+
+                KeyCode::Char('i') => {
+                    app.status = None;
+                    app.mode = Mode::ImportingConfig;
+                }
+
+                KeyCode::Char('r') if key.modifiers.contains(KeyModifiers::CONTROL) => app.redo(),
 
-                        KeyCode::Char('t') => {
-                            if let Some(ref path) = input_device {
-                                input::start_input_listener(path.clone(), tx.clone() as mpsc::Sender<u16>).await;
+                KeyCode::Char('d') => app.open_device_switcher(),
+
+                KeyCode::Char('t') => {
+                    if let Some(ref path) = input_device {
+                        match input::start_input_listener(path, tx.clone()) {
+                            Ok(listener) => {
+                                hidraw_listener = Some(listener);
                                 app.mode = Mode::Testing;
                                 app.last_input = None;
-                            } else {
-                                app.status = Some("Could not find mouse input device".into());
                             }
+                            Err(e) if e.kind() == io::ErrorKind::PermissionDenied => {
+                                let e = RatbagError::PermissionDenied { node: Some(path.clone()) };
+                                app.status = Some(error_status("Can't start test mode", &e));
+                            }
+                            Err(e) => app.status = Some(format!("Can't start test mode: {}: {}", path, e)),
                         }
+                    } else {
+                        app.status = Some("Could not find mouse input device".into());
+                    }
+                }
 
-                        KeyCode::Tab => {
-                            app.panel = if app.panel == Panel::Dpi {
-                                Panel::Buttons
-                            } else {
-                                Panel::Dpi
-                            };
-                            app.status = None;
-                        }
+                KeyCode::Tab => {
+                    app.next_panel();
+                    app.status = None;
+                }
+
+                KeyCode::Down | KeyCode::Char('j') => match app.panel {
+                    Panel::Profiles => app.next_profile(),
+                    Panel::Dpi => app.next_dpi(),
+                    Panel::ReportRate => app.next_rate(),
+                    Panel::Sensor => app.next_sensor(),
+                    Panel::Buttons => app.next_button(),
+                    Panel::Leds => app.next_led(),
+                },
 
-                        KeyCode::Down | KeyCode::Char('j') => match app.panel {
-                            Panel::Dpi => app.next_dpi(),
-                            Panel::Buttons => app.next_button(),
-                        },
-
-                        KeyCode::Up | KeyCode::Char('k') => match app.panel {
-                            Panel::Dpi => app.prev_dpi(),
-                            Panel::Buttons => app.prev_button(),
-                        },
-
-                        KeyCode::Enter => match app.panel {
-                            Panel::Dpi => {
-                                let new_dpi = app.selected_dpi();
-                                if new_dpi != app.device.dpi {
-                                    match app.device.set_dpi(&conn, new_dpi).await {
-                                        Ok(_) => app.status = Some(format!("DPI set to {}", new_dpi)),
-                                        Err(e) => app.status = Some(format!("Error: {}", e)),
-                                    }
-                                }
+                KeyCode::Up | KeyCode::Char('k') => match app.panel {
+                    Panel::Profiles => app.prev_profile(),
+                    Panel::Dpi => app.prev_dpi(),
+                    Panel::ReportRate => app.prev_rate(),
+                    Panel::Sensor => app.prev_sensor(),
+                    Panel::Buttons => app.prev_button(),
+                    Panel::Leds => app.prev_led(),
+                },
+
+                KeyCode::Enter => match app.panel {
+                    Panel::Profiles => {
+                        let profile = app.profile;
+                        if !app.profile().is_active {
+                            let label = app.profile().label();
+                            app.edit(|d| d.set_active_profile(profile));
+                            app.status = Some(format!("Active profile set to {}", label));
+                        }
+                    }
+                    Panel::Dpi => app.open_dpi_editor(),
+                    Panel::ReportRate => {
+                        let new_rate = app.selected_rate();
+                        if new_rate != app.profile().report_rate {
+                            let profile = app.profile;
+                            app.edit(|d| d.set_report_rate(profile, new_rate));
+                            app.status = Some(format!("Report rate set to {} Hz", new_rate));
+                        }
+                    }
+                    Panel::Sensor => {
+                        let row = app.sensor_state.selected().unwrap_or(0);
+                        match app.sensor_rows().get(row) {
+                            Some(SensorRow::AngleSnapping) => {
+                                let profile = app.profile;
+                                let enabled = app.profile().angle_snapping != Some(true);
+                                app.edit(|d| d.set_angle_snapping(profile, enabled));
+                                app.status = Some(format!(
+                                    "Angle snapping {}",
+                                    if enabled { "enabled" } else { "disabled" }
+                                ));
                             }
-                            Panel::Buttons => app.open_button_editor(),
-                        },
+                            Some(SensorRow::Debounce) => app.open_debounce_editor(),
+                            None => {}
+                        }
+                    }
+                    Panel::Buttons => app.open_button_editor(),
+                    Panel::Leds => app.open_led_editor(),
+                },
+
+                KeyCode::Char('+') | KeyCode::Char('=') | KeyCode::Char('-')
+                    if app.panel == Panel::Leds =>
+                {
+                    let profile = app.profile;
+                    let led_index = app.led_state.selected().unwrap_or(0);
+                    let current = app.selected_led().brightness;
+                    let brightness = if key.code == KeyCode::Char('-') {
+                        current.saturating_sub(LED_BRIGHTNESS_STEP)
+                    } else {
+                        (current + LED_BRIGHTNESS_STEP).min(MouseLed::MAX_BRIGHTNESS)
+                    };
+                    if brightness != current {
+                        app.edit(|d| d.set_led_brightness(profile, led_index, brightness));
+                        app.status = Some(format!("LED {} brightness set to {}", led_index, brightness));
+                    }
+                }
+
+                KeyCode::Char('a') if app.panel == Panel::Dpi => {
+                    let profile = app.profile;
+                    let res_index = app.dpi_state.selected().unwrap_or(0);
+                    let resolution = app.selected_resolution();
+                    if !resolution.is_active {
+                        // Choosing a disabled stage enables it as well
+                        let enabled = if resolution.is_disabled { "enabled and " } else { "" };
+                        app.edit(|d| d.set_active_resolution(profile, res_index));
+                        app.status = Some(format!("DPI stage {} {}is now active", res_index, enabled));
+                    }
+                }
+
+                KeyCode::Char('*') if app.panel == Panel::Dpi => {
+                    let profile = app.profile;
+                    let res_index = app.dpi_state.selected().unwrap_or(0);
+                    let resolution = app.selected_resolution();
+                    if !resolution.is_default {
+                        // Choosing a disabled stage enables it as well
+                        let enabled = if resolution.is_disabled { "enabled and " } else { "" };
+                        app.edit(|d| d.set_default_resolution(profile, res_index));
+                        app.status = Some(format!("DPI stage {} {}is now the default", res_index, enabled));
+                    }
+                }
+
+                KeyCode::Char('x') if app.panel == Panel::Dpi => {
+                    let profile = app.profile;
+                    let res_index = app.dpi_state.selected().unwrap_or(0);
+                    let resolution = app.selected_resolution();
+                    let disabled = !resolution.is_disabled;
+                    // The mouse needs an enabled stage to use and to start with
+                    if disabled && (resolution.is_active || resolution.is_default) {
+                        let role = if resolution.is_active { "active" } else { "default" };
+                        app.status = Some(format!("DPI stage {} is the {} stage and can't be disabled", res_index, role));
+                    } else {
+                        app.edit(|d| d.set_resolution_disabled(profile, res_index, disabled));
+                        app.status = Some(format!(
+                            "DPI stage {} {}",
+                            res_index,
+                            if disabled { "disabled" } else { "enabled" }
+                        ));
+                    }
+                }
+
+                KeyCode::Char('c') if app.panel == Panel::Leds => app.open_color_picker(),
+
+                KeyCode::Char('r') if app.panel == Panel::Buttons => app.start_macro_recording(),
+
+                KeyCode::Char('[') | KeyCode::Char(']') if app.panel == Panel::Leds => {
+                    let profile = app.profile;
+                    let led_index = app.led_state.selected().unwrap_or(0);
+                    let current = app.selected_led().effect_duration;
+                    let duration = if key.code == KeyCode::Char('[') {
+                        current.saturating_sub(LED_DURATION_STEP)
+                    } else {
+                        (current + LED_DURATION_STEP).min(MouseLed::MAX_EFFECT_DURATION)
+                    };
+                    if duration != current {
+                        app.edit(|d| d.set_led_effect_duration(profile, led_index, duration));
+                        app.status = Some(format!("LED {} effect duration set to {} ms", led_index, duration));
+                    }
+                }
+
+                _ => {}
+            },
+
+            Mode::EditingDpi => match key.code {
+                KeyCode::Esc => app.mode = Mode::Normal,
+                KeyCode::Down | KeyCode::Char('j') => app.next_popup(),
+                KeyCode::Up | KeyCode::Char('k') => app.prev_popup(),
+                KeyCode::Left | KeyCode::Char('h') => app.select_dpi_axis(Axis::X),
+                KeyCode::Right | KeyCode::Char('l') => app.select_dpi_axis(Axis::Y),
+                KeyCode::Char('L') => app.toggle_dpi_lock(),
+                KeyCode::Enter => {
+                    if let Some(dpi) = app.choose_dpi() {
+                        let profile = app.profile;
+                        let res_index = app.dpi_state.selected().unwrap_or(0);
+                        if dpi != app.selected_resolution().dpi {
+                            app.edit(|d| d.set_dpi(profile, res_index, dpi));
+                            app.status = Some(format!("DPI stage {} set to {}", res_index, dpi.label()));
+                        }
+                        app.mode = Mode::Normal;
+                    }
+                }
+                _ => {}
+            },
+
+            Mode::EditingButton => match key.code {
+                KeyCode::Esc => app.mode = Mode::Normal,
+                KeyCode::Down | KeyCode::Char('j') => app.next_popup(),
+                KeyCode::Up | KeyCode::Char('k') => app.prev_popup(),
+                KeyCode::Right | KeyCode::Char('l') | KeyCode::Tab => app.next_action_tab(),
+                KeyCode::Left | KeyCode::Char('h') | KeyCode::BackTab => app.prev_action_tab(),
+                KeyCode::Enter => {
+                    let profile = app.profile;
+                    let button_index = app.button_state.selected().unwrap_or(0);
+                    let action_index = app.popup_state.selected().unwrap_or(0);
+                    let action = app.popup_options[action_index].clone();
+                    match action {
+                        ButtonAction::Key(_) => {
+                            app.open_key_picker(KeyTarget::Button);
+                            continue;
+                        }
+                        ButtonAction::Macro(_) => {
+                            app.open_macro_editor();
+                            continue;
+                        }
+                        _ => {}
+                    }
+                    let label = action.label();
+                    app.edit(|d| d.set_button(profile, button_index, action));
+                    app.status = Some(format!("Button {} set to {}", button_index, label));
+                    app.mode = Mode::Normal;
+                }
+                _ => {}
+            },
+
+            Mode::PickingKey => {
+                let picker = app.key_picker.as_mut().unwrap();
+                match key.code {
+                    KeyCode::Esc => app.close_key_picker(),
+                    KeyCode::Down => picker.next(),
+                    KeyCode::Up => picker.prev(),
+                    KeyCode::Backspace => picker.pop(),
+                    KeyCode::Char(c) => picker.push(c),
+                    KeyCode::Tab => app.start_key_capture(app.key_target),
+                    KeyCode::Enter => {
+                        let Some(code) = picker.selected() else {
+                            continue;
+                        };
+                        let key = CapturedKey {
+                            code,
+                            modifiers: Vec::new(),
+                        };
+                        app.apply_key(key);
+                    }
+                    _ => {}
+                }
+            }
 
+            Mode::CapturingKey => match key.code {
+                KeyCode::Esc => app.close_key_picker(),
+                // The evdev reader sees the same keystroke with its real keycode
+                _ if app.key_capture.is_some() => app.capture_echoed = true,
+                _ => match keys::from_crossterm(&key) {
+                    Some(captured) => app.apply_key(captured),
+                    None => app.status = Some("That key has no Linux keycode; pick it from the list".into()),
+                },
+            },
+
+            Mode::EditingMacro => {
+                let editor = app.macro_editor.as_mut().unwrap();
+                if editor.delay_input.is_some() {
+                    match key.code {
+                        KeyCode::Esc => editor.delay_input = None,
+                        KeyCode::Enter => editor.submit_prompt(),
+                        KeyCode::Backspace => editor.pop_prompt(),
+                        KeyCode::Char(c) => editor.push_prompt(c),
                         _ => {}
-                    },
+                    }
+                    continue;
+                }
+                match key.code {
+                    KeyCode::Esc => {
+                        app.macro_editor = None;
+                        app.mode = Mode::Normal;
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => editor.next(),
+                    KeyCode::Up | KeyCode::Char('k') => editor.prev(),
+                    KeyCode::Char('J') => editor.move_down(),
+                    KeyCode::Char('K') => editor.move_up(),
+                    KeyCode::Char('a') => app.open_key_picker(KeyTarget::Macro),
+                    KeyCode::Char('p') => app.start_key_capture(KeyTarget::Macro),
+                    KeyCode::Char('w') => editor.start_delay_prompt(),
+                    KeyCode::Char('d') | KeyCode::Delete => editor.remove(),
+                    KeyCode::Char('t') => editor.toggle_direction(),
+                    KeyCode::Enter => {
+                        let profile = app.profile;
+                        let button_index = app.button_state.selected().unwrap_or(0);
+                        let steps = app.macro_editor.take().unwrap().steps;
+                        let action = ButtonAction::Macro(steps);
+                        let label = action.label();
+                        app.edit(|d| d.set_button(profile, button_index, action));
+                        app.status = Some(format!("Button {} set to {}", button_index, label));
+                        app.mode = Mode::Normal;
+                    }
+                    _ => {}
+                }
+            }
 
-                    Mode::EditingButton => match key.code {
-                        KeyCode::Esc => app.mode = Mode::Normal,
-                        KeyCode::Down | KeyCode::Char('j') => app.next_popup(),
-                        KeyCode::Up | KeyCode::Char('k') => app.prev_popup(),
-                        KeyCode::Enter => {
+            // Keystrokes while recording are read from evdev; only Esc matters here
+            Mode::RecordingMacro if app.recording.is_some() => {
+                if key.code == KeyCode::Esc {
+                    app.stop_macro_recording();
+                }
+            }
+
+            Mode::RecordingMacro => {
+                let recorder = app.macro_recorder.as_mut().unwrap();
+                match key.code {
+                    KeyCode::Esc => {
+                        app.macro_recorder = None;
+                        app.mode = Mode::Normal;
+                    }
+                    KeyCode::Down | KeyCode::Char('j') => recorder.next(),
+                    KeyCode::Up | KeyCode::Char('k') => recorder.prev(),
+                    KeyCode::Char('[') => recorder.trim_start(),
+                    KeyCode::Char(']') => recorder.trim_end(),
+                    KeyCode::Char('r') => app.start_macro_recording(),
+                    KeyCode::Enter => {
+                        let steps = app.macro_recorder.take().unwrap().steps;
+                        if steps.is_empty() {
+                            app.status = Some("Nothing recorded".into());
+                        } else {
+                            let profile = app.profile;
                             let button_index = app.button_state.selected().unwrap_or(0);
-                            let action_index = app.popup_state.selected().unwrap_or(0);
-                            let action = app.popup_options[action_index].clone();
+                            let action = ButtonAction::Macro(steps);
                             let label = action.label();
-                            match app.device.set_button(&conn, button_index, action).await {
-                                Ok(_) => app.status = Some(format!("Button {} set to {}", button_index, label)),
-                                Err(e) => app.status = Some(format!("Error: {}", e)),
-                            }
-                            app.mode = Mode::Normal;
+                            app.edit(|d| d.set_button(profile, button_index, action));
+                            app.status = Some(format!("Button {} set to {}", button_index, label));
                         }
-                        _ => {}
-                    },
+                        app.mode = Mode::Normal;
+                    }
+                    _ => {}
+                }
+            }
+
+            Mode::EditingLed => match key.code {
+                KeyCode::Esc => app.mode = Mode::Normal,
+                KeyCode::Down | KeyCode::Char('j') => app.next_popup(),
+                KeyCode::Up | KeyCode::Char('k') => app.prev_popup(),
+                KeyCode::Enter => {
+                    let profile = app.profile;
+                    let led_index = app.led_state.selected().unwrap_or(0);
+                    let mode_index = app.popup_state.selected().unwrap_or(0);
+                    let mode = app.led_options[mode_index];
+                    app.edit(|d| d.set_led_mode(profile, led_index, mode));
+                    app.status = Some(format!("LED {} set to {}", led_index, mode.label()));
+                    app.mode = Mode::Normal;
+                }
+                _ => {}
+            },
+
+            Mode::EditingDebounce => match key.code {
+                KeyCode::Esc => app.mode = Mode::Normal,
+                KeyCode::Down | KeyCode::Char('j') => app.next_popup(),
+                KeyCode::Up | KeyCode::Char('k') => app.prev_popup(),
+                KeyCode::Enter => {
+                    let profile = app.profile;
+                    let debounce_index = app.popup_state.selected().unwrap_or(0);
+                    if let Some(&debounce) = app.debounce_options.get(debounce_index) {
+                        app.edit(|d| d.set_debounce(profile, debounce));
+                        app.status = Some(format!("Debounce set to {} ms", debounce));
+                    }
+                    app.mode = Mode::Normal;
+                }
+                _ => {}
+            },
+
+            Mode::PickingColor => {
+                let step = if key.modifiers.contains(KeyModifiers::SHIFT) { 16 } else { 1 };
+                match key.code {
+                    KeyCode::Esc => {
+                        app.color_picker = None;
+                        app.mode = Mode::Normal;
+                    }
+                    KeyCode::Down => app.color_picker.as_mut().unwrap().next_field(),
+                    KeyCode::Up => app.color_picker.as_mut().unwrap().prev_field(),
+                    KeyCode::Right => app.color_picker.as_mut().unwrap().adjust(step),
+                    KeyCode::Left => app.color_picker.as_mut().unwrap().adjust(-step),
+                    KeyCode::Char(c) => app.color_picker.as_mut().unwrap().push_hex(c),
+                    KeyCode::Backspace => app.color_picker.as_mut().unwrap().pop_hex(),
+                    KeyCode::Enter => {
+                        let profile = app.profile;
+                        let led_index = app.led_state.selected().unwrap_or(0);
+                        let (r, g, b) = app.color_picker.take().unwrap().rgb;
+                        app.edit(|d| d.set_led_color(profile, led_index, (r as u32, g as u32, b as u32)));
+                        let (r, g, b) = app.selected_led().color;
+                        app.status = Some(format!(
+                            "LED {} colour set to #{:02x}{:02x}{:02x}",
+                            led_index, r, g, b
+                        ));
+                        app.mode = Mode::Normal;
+                    }
+                    _ => {}
+                }
+            }
+
+            Mode::SelectingDevice => match key.code {
+                KeyCode::Esc => app.mode = Mode::Normal,
+                KeyCode::Down | KeyCode::Char('j') => app.next_device(),
+                KeyCode::Up | KeyCode::Char('k') => app.prev_device(),
+                KeyCode::Enter => {
+                    let chosen = app.device_state.selected().unwrap_or(0);
+                    let jobs = std::mem::take(&mut app.jobs);
+                    let (daemon_down, disconnected) = (app.daemon_down, app.disconnected);
+                    app = App::new(std::mem::take(&mut app.devices), chosen);
+                    app.jobs = jobs;
+                    app.daemon_down = daemon_down;
+                    app.disconnected = disconnected;
+                    if let Err(e) = state::save_last_device(&app.device().name) {
+                        app.status = Some(format!("Could not remember device: {}", e));
+                    }
+                }
+                _ => {}
+            },
+
+            Mode::ReviewingChanges => match key.code {
+                KeyCode::Esc => app.mode = Mode::Normal,
+                KeyCode::Down | KeyCode::Char('j') => app.next_popup(),
+                KeyCode::Up | KeyCode::Char('k') => app.prev_popup(),
+                KeyCode::Char('d') | KeyCode::Delete => app.discard_pending_change(),
+                KeyCode::Char('x') => {
+                    app.edit(MouseDevice::discard_changes);
+                    app.status = Some("Discarded all unsaved changes".into());
+                    app.mode = Mode::Normal;
+                }
+                KeyCode::Char('w') | KeyCode::Enter => {
+                    app.mode = Mode::Normal;
+                    let device = app.device;
+                    if let Err(e) = write_device(&mut app, &mut worker, device) {
+                        let context = format!("Error writing {}", app.devices[device].name);
+                        app.status = Some(error_status(&context, &e));
+                    }
+                }
+                _ => {}
+            },
+
+            Mode::ImportingConfig => match key.code {
+                KeyCode::Esc => app.mode = Mode::Normal,
+                KeyCode::Enter => app.import_config(),
+                KeyCode::Backspace => {
+                    app.import_path.pop();
+                }
+                KeyCode::Char(c) => app.import_path.push(c),
+                _ => {}
+            },
 
-                    Mode::Testing => match key.code {
-                        KeyCode::Esc => {
-                            app.mode = Mode::Normal;
-                            app.last_input = None;
+            Mode::ConfirmingQuit => match key.code {
+                KeyCode::Esc => app.mode = Mode::Normal,
+                KeyCode::Char('q') => break,
+                // Quits once everything is written; primary button
+                // remaps are confirmed first
+                KeyCode::Char('w') => {
+                    app.mode = Mode::Normal;
+                    app.jobs.quit_pending = true;
+                    for device in 0..app.devices.len() {
+                        if let Err(e) = write_device(&mut app, &mut worker, device) {
+                            let context = format!("Error writing {}", app.devices[device].name);
+                            app.status = Some(error_status(&context, &e));
+                            app.jobs.quit_pending = false;
+                            break;
                         }
-                        _ => {}
-                    },
+                    }
+                }
+                _ => {}
+            },
+
+            // Only the keyboard counts as confirmation, since the remap
+            // may have left the mouse unusable
+            Mode::ConfirmingRemap => match key.code {
+                _ if app.reverts.is_empty() => app.mode = Mode::Normal,
+                KeyCode::Char('y') | KeyCode::Enter => {
+                    let revert = app.reverts.remove(0);
+                    app.status = Some(format!("Kept button changes on {}", revert.name));
+                    if app.reverts.is_empty() {
+                        app.mode = Mode::Normal;
+                    }
+                }
+                KeyCode::Char('n') | KeyCode::Esc => revert_remap(&mut app, &mut worker, 0),
+                _ => {}
+            },
+
+            Mode::Testing => {
+                if key.code == KeyCode::Esc {
+                    if let Some(listener) = hidraw_listener.take() {
+                        listener.abort();
+                    }
+                    // Clicks already read belong to this session of test mode
+                    while rx.try_recv().is_ok() {}
+                    app.mode = Mode::Normal;
+                    app.last_input = None;
                 }
             }
         }
@@ -487,5 +3029,10 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     disable_raw_mode()?;
     execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
 
+    // Also covers a device that was picked automatically rather than switched to
+    if let Err(e) = state::save_last_device(&app.device().name) {
+        eprintln!("ratbagtui: could not remember device: {}", e);
+    }
+
     Ok(())
 }
\ No newline at end of file
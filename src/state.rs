@@ -0,0 +1,29 @@
+use std::fs;
+use std::path::PathBuf;
+
+fn state_dir() -> Option<PathBuf> {
+    if let Some(dir) = std::env::var_os("XDG_STATE_HOME").filter(|d| !d.is_empty()) {
+        return Some(PathBuf::from(dir).join("ratbagtui"));
+    }
+    let home = std::env::var_os("HOME").filter(|d| !d.is_empty())?;
+    Some(PathBuf::from(home).join(".local/state/ratbagtui"))
+}
+
+/// Name of the device that was selected when ratbagtui last exited.
+///
+/// Devices are remembered by name rather than object path because ratbagd
+/// names its objects after the hidraw node, which changes between boots.
+pub fn load_last_device() -> Option<String> {
+    let path = state_dir()?.join("last-device");
+    let name = fs::read_to_string(path).ok()?;
+    let name = name.trim();
+    if name.is_empty() { None } else { Some(name.to_string()) }
+}
+
+pub fn save_last_device(name: &str) -> std::io::Result<()> {
+    let Some(dir) = state_dir() else {
+        return Ok(());
+    };
+    fs::create_dir_all(&dir)?;
+    fs::write(dir.join("last-device"), format!("{}\n", name))
+}